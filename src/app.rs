@@ -0,0 +1,1834 @@
+use crate::bookmark::Bookmarks;
+use crate::cache::{Cache, Entry};
+use crate::date;
+use crate::display::Display;
+use crate::feed;
+use crate::fetch::{self, ErrorKind, Fetched, Fetcher, Request, Validators};
+use crate::keymap::{Action, Gesture, Keymap};
+use crate::read::ReadLog;
+use crate::record::Outcome;
+use crate::remote::Command;
+use crate::scheduler::Scheduler;
+use crate::text::{text_width, wrap};
+use chrono::{DateTime, Utc};
+use image::error::{LimitError, LimitErrorKind};
+use image::imageops::colorops::invert;
+use image::imageops::{dither, overlay, BiLevel, FilterType};
+use image::io::Reader;
+use image::{GrayImage, ImageError, Luma};
+use imageproc::drawing::{draw_filled_rect_mut, draw_text_mut};
+use imageproc::rect::Rect;
+use qrcode::{Color, EcLevel, QrCode};
+use rusttype::{Font, Scale};
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::io::{self, Cursor};
+
+const BOOKMARKS: &str = "ブックマーク"; //カテゴリ一覧の最後に加える、ブックマークした記事のカテゴリ
+const MAX_IMAGE_PIXELS: u64 = 4096 * 4096; //デコードする画像の画素数の上限
+
+/*カテゴリ表示画面に必要な情報を保持する構造体*/
+struct CategoryPane {
+    names: Vec<String>,         //カテゴリ名のリスト(最後はブックマーク)
+    categories: Vec<GrayImage>, //カテゴリの文字と新着の件数を画像化したもののリスト
+    urls: Vec<String>,          //データ取得先のurlリスト
+    start_i: usize,             //表示領域の最初のインデックス
+    selected: usize,            //画面上で選択されているインデックス(0 <= x < 8 )
+}
+
+/*タイトル表示画面に必要な情報を保持する構造体*/
+struct TitlePane {
+    titles: TitleRows, //タイトルの行と本文や日付などタイトル以外の情報
+    start_i: usize,    //表示領域の最初のインデックス
+    selected: usize,   //画面上で選択されているインデックス(0 <= x < 8 )
+}
+
+/*本文画面に必要な情報を保持する構造体*/
+struct OverviewPane {
+    lines: Vec<String>, //画面の幅で折り返した本文の行
+    page: usize,        //表示しているページ(0始まり)
+}
+
+impl OverviewPane {
+    const PAGE_LINES: usize = 7; //複数ページのときの1ページの行数 最後の行はページ番号に使う
+
+    /*1画面に収まれば1ページ、収まらなければ7行ずつに分けたページ数*/
+    fn pages(&self) -> usize {
+        if self.lines.len() <= 8 {
+            1
+        } else {
+            self.lines.len().div_ceil(Self::PAGE_LINES)
+        }
+    }
+}
+
+/*上下に移動できる一覧の行*/
+trait Rows {
+    fn len(&self) -> usize;
+    fn row(&mut self, i: usize) -> GrayImage; //i番目の行の画像(128x8)
+}
+
+impl Rows for Vec<GrayImage> {
+    fn len(&self) -> usize {
+        <[GrayImage]>::len(self)
+    }
+
+    fn row(&mut self, i: usize) -> GrayImage {
+        self[i].clone()
+    }
+}
+
+/*
+ * タイトル一覧の行 件数が多くても表示する行だけを描画し、描画したものは覚えておく
+ * 未読の記事はタイトルの先頭に"●"を付ける
+ * "5分前"のような日付は表示するたびに求め、描画したときから変わっていれば描き直す
+ */
+struct TitleRows {
+    font: Font<'static>,
+    items: Vec<feed::Item>,
+    unread: Vec<bool>,
+    rendered: Vec<Option<(Option<String>, GrayImage)>>, //描画したときの日付と画像
+}
+
+impl TitleRows {
+    fn new(font: Font<'static>, items: Vec<feed::Item>, unread: Vec<bool>) -> Self {
+        let rendered = items.iter().map(|_| None).collect();
+        Self {
+            font,
+            items,
+            unread,
+            rendered,
+        }
+    }
+
+    /*i番目の記事の未読の印を変え、変わったら描き直すようにする*/
+    fn set_unread(&mut self, i: usize, unread: bool) {
+        if self.unread[i] != unread {
+            self.unread[i] = unread;
+            self.rendered[i] = None;
+        }
+    }
+}
+
+impl Rows for TitleRows {
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn row(&mut self, i: usize) -> GrayImage {
+        let item = &self.items[i];
+        let age = item
+            .pub_date
+            .as_ref()
+            .map(|pub_date| pub_date.relative(Utc::now()));
+        match &self.rendered[i] {
+            Some((rendered_age, img)) if *rendered_age == age => img.clone(),
+            _ => {
+                let title = if self.unread[i] {
+                    format!("●{}", item.title)
+                } else {
+                    item.title.clone()
+                };
+                let img = render_title_row(&self.font, &title, age.as_deref());
+                self.rendered[i] = Some((age, img.clone()));
+                img
+            }
+        }
+    }
+}
+
+/*状態を表す列挙型*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum State {
+    Category,
+    Title,
+    Overview,
+    Image,
+    Info,
+    Qr,    //記事のリンクのQRコード
+    Error, //フィードを読み込めなかった、またはブックマークがない
+}
+
+/*画面の状態とボタン入力に応じた画面遷移を管理する構造体*/
+pub struct App<D: Display> {
+    display: D,
+    font: Font<'static>,
+    category_pane: CategoryPane,
+    title_pane: TitlePane,
+    overview_pane: OverviewPane,
+    state: State,
+    keymap: Keymap,
+    scheduler: Scheduler,
+    fetcher: Fetcher,
+    cache: Option<Cache>,                    //取得したフィードを保存しておく先
+    feeds: Vec<Option<Entry>>,               //カテゴリごとの取得済みのフィード
+    stale: Vec<bool>,                        //最後の取得に失敗したか、保存先から読み出したままか
+    seen: Vec<HashSet<String>>,              //カテゴリごとの最後にタイトル一覧を表示したときの記事
+    read: ReadLog,                           //既読の記事
+    bookmarks: Bookmarks,                    //ブックマークした記事
+    progress: Option<(usize, usize)>,        //起動時の先読みで取得し終えたカテゴリの数と全体の数
+    recorded: Option<Vec<(usize, Outcome)>>, //記録中なら、入力の処理の中で取得したカテゴリと結果
+    replayed: Option<VecDeque<(usize, Outcome)>>, //記録の再生中なら、取得の代わりに使うカテゴリと結果
+}
+
+impl<D: Display> App<D> {
+    /*
+     * カテゴリ名とurlの組から最初の画面を表示する schedulerはカテゴリと同じ数のフィードを扱うもの
+     * カテゴリ一覧の最後にはブックマークを加える
+     */
+    pub fn new(
+        mut display: D,
+        font: Font<'static>,
+        feeds: &[(&str, &str)],
+        keymap: Keymap,
+        scheduler: Scheduler,
+        fetcher: Fetcher,
+        cache: Option<Cache>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let names = feeds
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .chain(Some(BOOKMARKS.to_string()))
+            .collect::<Vec<_>>();
+        let mut categories = names
+            .iter()
+            .map(|name| render_row(&font, name))
+            .collect::<Vec<_>>();
+        let urls = feeds
+            .iter()
+            .map(|(_, url)| url.to_string())
+            .collect::<Vec<_>>();
+
+        /*最初に表示する画面を生成*/
+        display.draw_image(&render_page(&mut categories, 0, 0), 0, 0)?;
+        let titles = TitleRows::new(font.clone(), Vec::new(), Vec::new());
+        //既読の記録を読み出せなければ、壊さないよう保存もしない
+        let read = match &cache {
+            Some(cache) => cache.read_log().unwrap_or_else(|e| {
+                eprintln!("failed to load the read state: {}", e);
+                ReadLog::default()
+            }),
+            None => ReadLog::default(),
+        };
+        let bookmarks = match &cache {
+            Some(cache) => cache.bookmarks().unwrap_or_else(|e| {
+                eprintln!("failed to load the bookmarks: {}", e);
+                Bookmarks::default()
+            }),
+            None => Bookmarks::default(),
+        };
+
+        Ok(Self {
+            display,
+            font,
+            category_pane: CategoryPane {
+                names,
+                categories,
+                urls,
+                start_i: 0,
+                selected: 0,
+            },
+            title_pane: TitlePane {
+                titles,
+                start_i: 0,
+                selected: 0,
+            },
+            overview_pane: OverviewPane {
+                lines: Vec::new(),
+                page: 0,
+            },
+            state: State::Category,
+            keymap,
+            scheduler,
+            fetcher,
+            cache,
+            feeds: feeds.iter().map(|_| None).collect(),
+            stale: feeds.iter().map(|_| false).collect(),
+            seen: feeds.iter().map(|_| HashSet::new()).collect(),
+            read,
+            bookmarks,
+            progress: None,
+            recorded: None,
+            replayed: None,
+        })
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /*現在選択されている行の全体でのインデックス*/
+    pub fn selected_index(&self) -> usize {
+        match self.state {
+            State::Category | State::Error => {
+                self.category_pane.start_i + self.category_pane.selected
+            }
+            State::Title | State::Overview | State::Image | State::Info | State::Qr => {
+                self.title_pane.start_i + self.title_pane.selected
+            }
+        }
+    }
+
+    pub fn display(&self) -> &D {
+        &self.display
+    }
+
+    /*現在の画面を"category 3"や"title 3 5"のような文字列で表す*/
+    pub fn screen(&self) -> String {
+        let category = self.category_pane.start_i + self.category_pane.selected;
+        let title = self.title_pane.start_i + self.title_pane.selected;
+        match self.state {
+            State::Category => format!("category {}", category),
+            State::Title => format!("title {} {}", category, title),
+            State::Overview => format!("overview {} {}", category, title),
+            State::Image => format!("image {} {}", category, title),
+            State::Info => format!("info {} {}", category, title),
+            State::Qr => format!("qr {} {}", category, title),
+            State::Error => format!("error {}", category),
+        }
+    }
+
+    /*ボタンの押し方を現在の画面のキー割り当てに従って動作に変換し、画面を遷移させる*/
+    pub async fn gesture(&mut self, gesture: Gesture) -> Result<(), Box<dyn Error>> {
+        match self.keymap.action(self.state, gesture) {
+            Some(action) => self.action(action).await,
+            None => Ok(()),
+        }
+    }
+
+    /*動作に応じて画面を遷移させる 現在の画面で意味を持たない動作は無視する*/
+    pub async fn action(&mut self, action: Action) -> Result<(), Box<dyn Error>> {
+        match (self.state, action) {
+            (State::Category, Action::Down) => {
+                //下に移動
+                let pane = &mut self.category_pane;
+                move_down(
+                    &mut self.display,
+                    &mut pane.categories,
+                    &mut pane.start_i,
+                    &mut pane.selected,
+                )?;
+                self.draw_status()?;
+            }
+            (State::Category, Action::Up) => {
+                //上に移動
+                let pane = &mut self.category_pane;
+                move_up(
+                    &mut self.display,
+                    &mut pane.categories,
+                    &mut pane.start_i,
+                    &mut pane.selected,
+                )?;
+                self.draw_status()?;
+            }
+            (_, Action::Refresh) => {
+                //選択したカテゴリを取得し直してタイトル一覧画面へ遷移
+                let i = self.category_pane.start_i + self.category_pane.selected;
+                self.open_category(i, true).await?;
+            }
+            (State::Category, Action::Select) => {
+                //選択したカテゴリのタイトル一覧画面へ遷移
+                let i = self.category_pane.start_i + self.category_pane.selected;
+                self.open_category(i, false).await?;
+            }
+            (State::Title, Action::Down) => {
+                let pane = &mut self.title_pane;
+                move_down(
+                    &mut self.display,
+                    &mut pane.titles,
+                    &mut pane.start_i,
+                    &mut pane.selected,
+                )?;
+                self.draw_status()?;
+            }
+            (State::Title, Action::Up) => {
+                let pane = &mut self.title_pane;
+                move_up(
+                    &mut self.display,
+                    &mut pane.titles,
+                    &mut pane.start_i,
+                    &mut pane.selected,
+                )?;
+                self.draw_status()?;
+            }
+            (State::Title, Action::Back) | (State::Error, Action::Back) | (_, Action::Home) => {
+                self.state = State::Category;
+                self.show_categories()?;
+            }
+            (State::Title, Action::Select)
+            | (State::Image, Action::Back)
+            | (State::Info, Action::Back)
+            | (State::Qr, Action::Back) => {
+                //本文を開いた記事は既読にする 画像などから戻ったときは同じページを表示する
+                let i = self.title_pane.start_i + self.title_pane.selected;
+                self.set_read(i, true);
+                if self.state == State::Title {
+                    self.overview_pane = OverviewPane {
+                        lines: self.overview_lines(i),
+                        page: 0,
+                    };
+                }
+                let img = self.render_overview();
+                self.display.draw_image(&img, 0, 0)?;
+                self.state = State::Overview;
+            }
+            (State::Overview, Action::Down) => {
+                //次のページへ 最後のページでは何もしない
+                let pane = &mut self.overview_pane;
+                if pane.page + 1 < pane.pages() {
+                    pane.page += 1;
+                    let img = self.render_overview();
+                    self.display.draw_image(&img, 0, 0)?;
+                }
+            }
+            (State::Overview, Action::Up) => {
+                let pane = &mut self.overview_pane;
+                if pane.page > 0 {
+                    pane.page -= 1;
+                    let img = self.render_overview();
+                    self.display.draw_image(&img, 0, 0)?;
+                }
+            }
+            (State::Overview, Action::Select) => {
+                //画像のない記事では何もしない
+                let i = self.title_pane.start_i + self.title_pane.selected;
+                if let Some(url) = self.title_pane.titles.items[i].image.clone() {
+                    self.open_image(&url).await?;
+                }
+            }
+            (State::Overview, Action::Info) => {
+                let i = self.title_pane.start_i + self.title_pane.selected;
+                let img = self.render_info(i);
+                self.display.draw_image(&img, 0, 0)?;
+                self.state = State::Info;
+            }
+            (State::Overview, Action::Qr) => {
+                //リンクのない記事ではその旨を表示する
+                let i = self.title_pane.start_i + self.title_pane.selected;
+                let img = match &self.title_pane.titles.items[i].link {
+                    Some(link) => render_qr(&self.font, link),
+                    None => render_message(&self.font, "リンクがありません"),
+                };
+                self.display.draw_image(&img, 0, 0)?;
+                self.state = State::Qr;
+            }
+            (State::Overview, Action::Back) => self.redraw_titles()?,
+            (State::Category, Action::ReadAll) | (State::Title, Action::ReadAll) => {
+                let i = self.category_pane.start_i + self.category_pane.selected;
+                self.read_all(i)?;
+            }
+            (State::Title, Action::Bookmark) | (State::Overview, Action::Bookmark) => {
+                let i = self.title_pane.start_i + self.title_pane.selected;
+                self.toggle_bookmark(i)?;
+            }
+            (State::Title, Action::Unread) | (State::Overview, Action::Unread) => {
+                //未読に戻した記事は本文からタイトル一覧に戻る
+                let i = self.title_pane.start_i + self.title_pane.selected;
+                self.set_read(i, false);
+                self.redraw_titles()?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /*指定したカテゴリを選択した状態にしてタイトル一覧画面へ遷移する*/
+    pub async fn goto_category(&mut self, i: usize) -> Result<(), Box<dyn Error>> {
+        let pane = &mut self.category_pane;
+        if i >= pane.categories.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "No such category").into());
+        }
+        if i < pane.start_i {
+            pane.start_i = i;
+        } else if i >= pane.start_i + 8 {
+            pane.start_i = i - 7;
+        }
+        pane.selected = i - pane.start_i;
+        self.open_category(i, false).await
+    }
+
+    /*
+     * 取得し直す時期になったカテゴリの取得の内容を返す
+     * 取得は呼び出し側がボタン操作の処理と並行して行い、結果はrefreshedで反映する
+     * タイトル一覧を表示中なら、"5分前"のような日付が古くならないよう描き直す
+     */
+    pub fn tick(&mut self) -> Vec<Request> {
+        if self.state == State::Title {
+            if let Err(e) = self.redraw_titles() {
+                eprintln!("{}", e);
+            }
+        }
+        let now = Utc::now();
+        let current = self.category_pane.start_i + self.category_pane.selected;
+        //本文などを読んでいる間に一覧が入れ替わらないよう、戻るまで後回しにする
+        let reading = matches!(
+            self.state,
+            State::Overview | State::Image | State::Info | State::Qr
+        );
+        self.scheduler
+            .due(now)
+            .into_iter()
+            .filter(|&i| !(i == current && reading))
+            .map(|i| {
+                //結果が届くまでは定期的な取得の対象にしない
+                self.scheduler.postpone(i, now);
+                self.request(i)
+            })
+            .collect()
+    }
+
+    /*
+     * 定期的な取得で取得したi番目のカテゴリの結果を反映し、カテゴリ一覧の新着の件数を更新する
+     * そのタイトル一覧を表示中ならタイトル一覧も更新する
+     */
+    pub fn refreshed(
+        &mut self,
+        i: usize,
+        fetched_at: DateTime<Utc>,
+        result: Result<Fetched, fetch::Error>,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.apply(i, fetched_at, result) {
+            Ok(()) => self.update_badge(i)?,
+            Err(e) => eprintln!("{}", e),
+        }
+        //読み込めなかったカテゴリを開いたままなら、読み込めた時点でタイトル一覧にする
+        //取得に失敗したときもキャッシュの表示に切り替えるため描画し直す
+        let current = self.category_pane.start_i + self.category_pane.selected;
+        if i == current
+            && matches!(self.state, State::Title | State::Error)
+            && self.feeds[i].is_some()
+        {
+            let (start_i, selected) = (self.title_pane.start_i, self.title_pane.selected);
+            self.show_titles(i, start_i, selected)?;
+        }
+        Ok(())
+    }
+
+    /*
+     * 起動時の先読みを始め、進み具合の表示を出す
+     * 保存しておいたフィードを読み出し、カテゴリごとの取得の内容を返す
+     */
+    pub fn start_prefetch(&mut self) -> Result<Vec<Request>, Box<dyn Error>> {
+        let now = Utc::now();
+        let requests = (0..self.feeds.len())
+            .map(|i| {
+                self.load_cache(i);
+                //前回までに表示した記事は覚えていないので、保存しておいた記事は新着として数えない
+                if let Some(entry) = &self.feeds[i] {
+                    self.seen[i] = entry.feed.items.iter().map(|item| item.id()).collect();
+                }
+                //先読みの結果が届くまでは定期的な取得の対象にしない
+                self.scheduler.postpone(i, now);
+                self.request(i)
+            })
+            .collect::<Vec<_>>();
+        for i in 0..self.feeds.len() {
+            self.update_badge(i)?;
+        }
+        self.progress = Some((0, requests.len()));
+        self.draw_status()?;
+        Ok(requests)
+    }
+
+    /*先読みで取得したi番目のカテゴリの結果を反映し、すべて終わったら進み具合の表示を消す*/
+    pub fn prefetched(
+        &mut self,
+        i: usize,
+        fetched_at: DateTime<Utc>,
+        result: Result<Fetched, fetch::Error>,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.apply(i, fetched_at, result) {
+            Ok(()) => self.update_badge(i)?,
+            Err(e) => eprintln!("{}", e),
+        }
+        if let Some((done, total)) = &mut self.progress {
+            *done += 1;
+            if done < total {
+                return self.draw_status();
+            }
+            self.progress = None;
+            if self.state == State::Category {
+                self.show_categories()?;
+            }
+        }
+        Ok(())
+    }
+
+    /*入力の処理の中で取得した結果を、take_fetchedで取り出せるよう残しておく*/
+    pub fn record_fetches(&mut self) {
+        self.recorded = Some(Vec::new());
+    }
+
+    /*残しておいた取得の結果を取り出す*/
+    pub fn take_fetched(&mut self) -> Vec<(usize, Outcome)> {
+        match &mut self.recorded {
+            Some(recorded) => std::mem::take(recorded),
+            None => Vec::new(),
+        }
+    }
+
+    /*これ以降は通信せず、replay_fetchedで渡された結果を取得の結果として使う*/
+    pub fn replay_fetches(&mut self) {
+        self.replayed = Some(VecDeque::new());
+    }
+
+    /*次にi番目のカテゴリを取得したときの結果を渡す*/
+    pub fn replay_fetched(&mut self, i: usize, outcome: Outcome) {
+        self.replayed
+            .get_or_insert_with(VecDeque::new)
+            .push_back((i, outcome));
+    }
+
+    /*リモートから受け取ったコマンドを処理する*/
+    pub async fn command(&mut self, command: &Command) -> Result<(), Box<dyn Error>> {
+        match command {
+            Command::Action(action) => self.action(*action).await,
+            Command::Goto(i) => self.goto_category(*i).await,
+            Command::Status => Ok(()),
+        }
+    }
+
+    /*i番目のタイトルの本文を画面の幅で折り返した行*/
+    fn overview_lines(&self, i: usize) -> Vec<String> {
+        let item = &self.title_pane.titles.items[i];
+        //content:encodedなどの本文があれば概要より優先する
+        let s = item
+            .content
+            .as_deref()
+            .or(item.description.as_deref())
+            .unwrap_or("Not Found");
+        let mut lines = wrap(&self.font, s, 128);
+        //日付があれば1行目に見出しとして表示する
+        if let Some(pub_date) = &item.pub_date {
+            lines.insert(0, pub_date.relative(Utc::now()));
+        }
+        lines
+    }
+
+    /*本文画面の表示しているページを生成する 複数ページなら右下に"2/5"のようなページ番号を重ねる*/
+    fn render_overview(&self) -> GrayImage {
+        let pane = &self.overview_pane;
+        let pages = pane.pages();
+        if pages == 1 {
+            return render_lines(&self.font, &pane.lines);
+        }
+        let start = pane.page * OverviewPane::PAGE_LINES;
+        let end = (start + OverviewPane::PAGE_LINES).min(pane.lines.len());
+        let mut img = render_lines(&self.font, &pane.lines[start..end]);
+        let label = render_label(&self.font, &format!("{}/{}", pane.page + 1, pages));
+        overlay(&mut img, &label, 128 - label.width(), 56);
+        img
+    }
+
+    /*i番目のタイトルの著者やカテゴリなどの情報画面を生成する*/
+    fn render_info(&self, i: usize) -> GrayImage {
+        let item = &self.title_pane.titles.items[i];
+        let mut s = item.title.clone();
+        if let Some(author) = &item.author {
+            s.push_str(&format!("\n著者: {}", author));
+        }
+        if !item.categories.is_empty() {
+            s.push_str(&format!("\nカテゴリ: {}", item.categories.join("、")));
+        }
+        if let Some(pub_date) = &item.pub_date {
+            s.push_str(&format!("\n日付: {}", pub_date.raw.trim()));
+        }
+        render_lines(&self.font, &wrap(&self.font, &s, 128))
+    }
+
+    /*画像を取得して表示する 取得や変換に失敗したらその旨を表示する 記録の再生中は取得しない*/
+    async fn open_image(&mut self, url: &str) -> Result<(), Box<dyn Error>> {
+        let result = match self.replayed {
+            Some(_) => Err(format!("{}: images are not fetched during a replay", url).into()),
+            None => fetch_image(&self.fetcher, url).await,
+        };
+        let img = match result {
+            Ok(img) => img,
+            Err(e) => {
+                eprintln!("{}", e);
+                render_message(&self.font, "画像を表示できません")
+            }
+        };
+        self.display.draw_image(&img, 0, 0)?;
+        self.state = State::Image;
+        Ok(())
+    }
+
+    /*
+     * i番目のカテゴリのタイトル一覧画面へ遷移する
+     * 取得して間もないフィードはforceを指定しない限り取得し直さない
+     */
+    async fn open_category(&mut self, i: usize, force: bool) -> Result<(), Box<dyn Error>> {
+        if self.is_bookmarks(i) {
+            return self.show_titles(i, 0, 0);
+        }
+        if self.feeds[i].is_none() {
+            self.load_cache(i);
+        }
+        //保存先から読み出しただけのものは取得し直す
+        if force
+            || self.feeds[i].is_none()
+            || self.stale[i]
+            || !self.scheduler.is_fresh(i, Utc::now())
+        {
+            if let Err(e) = self.fetch(i).await {
+                eprintln!("{}", e);
+                //取得や読み込みに失敗しても終了せず、前回の内容がなければその旨を表示して戻れるようにする
+                if self.feeds[i].is_none() {
+                    let img = render_fetch_error(&self.font, &e);
+                    self.display.draw_image(&img, 0, 0)?;
+                    self.state = State::Error;
+                    return Ok(());
+                }
+            }
+        }
+        self.show_titles(i, 0, 0)
+    }
+
+    /*i番目のカテゴリのフィードが保存されていれば読み出す 読み出したものは取得し直すまで古いものとして扱う*/
+    fn load_cache(&mut self, i: usize) {
+        let url = &self.category_pane.urls[i];
+        if let Some(cache) = &self.cache {
+            match cache.load(url) {
+                Ok(Some(entry)) => {
+                    self.feeds[i] = Some(entry);
+                    self.stale[i] = true;
+                }
+                Ok(None) => (),
+                Err(e) => eprintln!("failed to load the cache of {}: {}", url, e),
+            }
+        }
+    }
+
+    /*i番目のカテゴリのフィードを保存する 失敗しても表示は続ける*/
+    fn save_cache(&self, i: usize) {
+        if let (Some(cache), Some(entry)) = (&self.cache, &self.feeds[i]) {
+            if let Err(e) = cache.save(entry) {
+                eprintln!("failed to save the cache of {}: {}", entry.url, e);
+            }
+        }
+    }
+
+    /*i番目のカテゴリの、最後にタイトル一覧を表示したときにはなかった記事の件数*/
+    fn unread_count(&self, i: usize) -> usize {
+        match self.feeds.get(i) {
+            Some(Some(entry)) => entry
+                .feed
+                .items
+                .iter()
+                .filter(|item| !self.seen[i].contains(&item.id()))
+                .count(),
+            _ => 0,
+        }
+    }
+
+    /*i番目のカテゴリの行を新着の件数を添えて描き直す カテゴリ一覧を表示中なら画面にも反映する*/
+    fn update_badge(&mut self, i: usize) -> Result<(), Box<dyn Error>> {
+        let count = self.unread_count(i);
+        let badge = if count > 0 {
+            Some(format!("新着{}", count))
+        } else {
+            None
+        };
+        let pane = &mut self.category_pane;
+        pane.categories[i] = render_title_row(&self.font, &pane.names[i], badge.as_deref());
+        if self.state == State::Category && (pane.start_i..pane.start_i + 8).contains(&i) {
+            let mut img = pane.categories[i].clone();
+            if i == pane.start_i + pane.selected {
+                invert(&mut img);
+            }
+            self.display.draw_image(&img, 0, (i - pane.start_i) as u8)?;
+            self.draw_status()?;
+        }
+        Ok(())
+    }
+
+    /*
+     * 画面の右下に状態を重ねる
+     * カテゴリ一覧では先読みの進み具合を、キャッシュを表示しているタイトル一覧では"キャッシュ"と取得してからの時間を表示する
+     */
+    fn draw_status(&mut self) -> Result<(), Box<dyn Error>> {
+        let i = self.category_pane.start_i + self.category_pane.selected;
+        let label = match (self.state, self.stale.get(i), self.feeds.get(i)) {
+            (State::Category, _, _) => self
+                .progress
+                .map(|(done, total)| format!("取得中 {}/{}", done, total)),
+            (State::Title, Some(true), Some(Some(entry))) => Some(format!(
+                "キャッシュ {}",
+                date::relative(entry.fetched_at, Utc::now())
+            )),
+            _ => None,
+        };
+        if let Some(label) = label {
+            let img = render_label(&self.font, &label);
+            self.display
+                .draw_image(&img, (128 - img.width()) as u8, 7)?;
+        }
+        Ok(())
+    }
+
+    /*カテゴリ一覧を描き直す*/
+    fn show_categories(&mut self) -> Result<(), Box<dyn Error>> {
+        let pane = &mut self.category_pane;
+        let img = render_page(&mut pane.categories, pane.start_i, pane.selected);
+        self.display.draw_image(&img, 0, 0)?;
+        self.draw_status()
+    }
+
+    /*表示中のタイトル一覧を描き直す*/
+    fn redraw_titles(&mut self) -> Result<(), Box<dyn Error>> {
+        let pane = &mut self.title_pane;
+        let img = render_page(&mut pane.titles, pane.start_i, pane.selected);
+        self.display.draw_image(&img, 0, 0)?;
+        self.state = State::Title;
+        self.draw_status()
+    }
+
+    /*表示中のタイトル一覧のi番目の記事を既読または未読にして保存する*/
+    fn set_read(&mut self, i: usize, read: bool) {
+        let id = self.title_pane.titles.items[i].id();
+        let changed = if read {
+            self.read.mark_read(&id)
+        } else {
+            self.read.mark_unread(&id)
+        };
+        self.title_pane.titles.set_unread(i, !read);
+        if changed {
+            self.save_read();
+        }
+    }
+
+    /*i番目のカテゴリの記事をすべて既読にし、新着の件数も消す タイトル一覧を表示中なら描き直す*/
+    fn read_all(&mut self, i: usize) -> Result<(), Box<dyn Error>> {
+        let ids = self
+            .items(i)
+            .iter()
+            .map(|item| item.id())
+            .collect::<HashSet<_>>();
+        for id in &ids {
+            self.read.mark_read(id);
+        }
+        self.save_read();
+        if let Some(seen) = self.seen.get_mut(i) {
+            *seen = ids;
+        }
+        self.update_badge(i)?;
+        if self.state == State::Title {
+            for j in 0..self.title_pane.titles.len() {
+                self.title_pane.titles.set_unread(j, false);
+            }
+            self.redraw_titles()?;
+        }
+        Ok(())
+    }
+
+    /*
+     * 表示中のタイトル一覧のi番目の記事をブックマークする(してあれば外す)
+     * ブックマークの一覧で外した記事は一覧から除き(本文からならタイトル一覧に戻る)、それ以外では結果を画面の右下に表示する
+     */
+    fn toggle_bookmark(&mut self, i: usize) -> Result<(), Box<dyn Error>> {
+        let added = self.bookmarks.toggle(&self.title_pane.titles.items[i]);
+        if let Err(e) = self.bookmarks.save() {
+            eprintln!("failed to save the bookmarks: {}", e);
+        }
+        let category = self.category_pane.start_i + self.category_pane.selected;
+        if matches!(self.state, State::Title | State::Overview) && self.is_bookmarks(category) {
+            let (start_i, selected) = (self.title_pane.start_i, self.title_pane.selected);
+            return self.show_titles(category, start_i, selected);
+        }
+        let label = if added {
+            "ブックマークしました"
+        } else {
+            "ブックマークを外しました"
+        };
+        let img = render_label(&self.font, label);
+        self.display
+            .draw_image(&img, (128 - img.width()) as u8, 7)?;
+        Ok(())
+    }
+
+    /*i番目のカテゴリがブックマークか*/
+    fn is_bookmarks(&self, i: usize) -> bool {
+        i == self.feeds.len()
+    }
+
+    /*i番目のカテゴリの記事 ブックマークならブックマークした記事*/
+    fn items(&self, i: usize) -> &[feed::Item] {
+        match self.feeds.get(i) {
+            Some(entry) => entry
+                .as_ref()
+                .map(|entry| &entry.feed.items[..])
+                .unwrap_or_default(),
+            None => self.bookmarks.items(),
+        }
+    }
+
+    /*既読の記録を保存する 失敗しても表示は続ける*/
+    fn save_read(&self) {
+        if let Err(e) = self.read.save() {
+            eprintln!("failed to save the read state: {}", e);
+        }
+    }
+
+    /*i番目のカテゴリを取得し直すための内容*/
+    fn request(&self, i: usize) -> Request {
+        Request {
+            index: i,
+            url: self.category_pane.urls[i].clone(),
+            validators: self.validators(i),
+        }
+    }
+
+    /*i番目のカテゴリの次回の条件付きGETに使う値*/
+    fn validators(&self, i: usize) -> Validators {
+        self.feeds[i]
+            .as_ref()
+            .map(|entry| entry.validators.clone())
+            .unwrap_or_default()
+    }
+
+    /*
+     * i番目のカテゴリのフィードを取得して保持する
+     * 記録の再生中は通信せず、渡されている結果を使う 結果がなければ保持しているものが変わっていないものとする
+     */
+    async fn fetch(&mut self, i: usize) -> Result<(), fetch::Error> {
+        let now = Utc::now();
+        let url = &self.category_pane.urls[i];
+        let result = match &mut self.replayed {
+            Some(replayed) => match replayed.iter().position(|(j, _)| *j == i) {
+                Some(n) => replayed.remove(n).unwrap().1.into_result(url),
+                None if self.feeds[i].is_some() => Ok(Fetched::NotModified),
+                None => Err(fetch::Error {
+                    url: url.to_string(),
+                    kind: ErrorKind::Recorded("not recorded".to_string()),
+                }),
+            },
+            None => self.fetcher.fetch(url, &self.validators(i)).await,
+        };
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push((i, Outcome::new(&result)));
+        }
+        self.apply(i, now, result)
+    }
+
+    /*nowに始めたi番目のカテゴリの取得の結果を保持する*/
+    fn apply(
+        &mut self,
+        i: usize,
+        now: DateTime<Utc>,
+        result: Result<Fetched, fetch::Error>,
+    ) -> Result<(), fetch::Error> {
+        //後から始めた取得(カテゴリを開いたときなど)の結果を反映済みなら、遅れて届いた古い結果は捨てる
+        if matches!(&self.feeds[i], Some(entry) if entry.fetched_at > now) {
+            return Ok(());
+        }
+        let url = &self.category_pane.urls[i];
+        match result {
+            Ok(Fetched::Modified(feed, validators)) => {
+                self.scheduler.fetched(i, now, feed.schedule.clone());
+                self.feeds[i] = Some(Entry {
+                    url: url.to_string(),
+                    fetched_at: now,
+                    validators,
+                    feed: *feed,
+                });
+            }
+            Ok(Fetched::NotModified) => {
+                //変わっていなければ読み込み済みのitemをそのまま使う
+                if let Some(entry) = &mut self.feeds[i] {
+                    self.scheduler.fetched(i, now, entry.feed.schedule.clone());
+                    entry.fetched_at = now;
+                }
+            }
+            Err(e) => {
+                self.scheduler.postpone(i, now);
+                self.stale[i] = self.feeds[i].is_some();
+                return Err(e);
+            }
+        }
+        self.stale[i] = false;
+        self.save_cache(i);
+        Ok(())
+    }
+
+    /*
+     * 保持しているi番目のカテゴリのフィードでタイトル一覧画面を表示する
+     * 選択位置は指定したものを使い、タイトルの数が減っていれば最後の行に合わせる
+     * ブックマークが空ならその旨を表示してカテゴリ一覧に戻れるようにする
+     */
+    fn show_titles(
+        &mut self,
+        i: usize,
+        start_i: usize,
+        selected: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let items = self.items(i).to_vec();
+        //記事がなければその旨を表示し、戻る以外の操作を受け付けないようにする
+        if items.is_empty() {
+            let message = if self.is_bookmarks(i) {
+                "ブックマークはありません"
+            } else {
+                "記事はありません"
+            };
+            let img = render_message(&self.font, message);
+            self.display.draw_image(&img, 0, 0)?;
+            self.state = State::Error;
+            return Ok(());
+        }
+
+        //表示した記事は新着として数えない
+        if let Some(seen) = self.seen.get_mut(i) {
+            *seen = items.iter().map(|item| item.id()).collect();
+        }
+        let unread = items
+            .iter()
+            .map(|item| !self.read.is_read(&item.id()))
+            .collect();
+        let mut titles = TitleRows::new(self.font.clone(), items, unread);
+        let index = (start_i + selected).min(titles.len().saturating_sub(1));
+        let start_i = start_i.min(index);
+        let selected = index - start_i;
+        self.display
+            .draw_image(&render_page(&mut titles, start_i, selected), 0, 0)?;
+        self.state = State::Title;
+        self.title_pane = TitlePane {
+            titles,
+            start_i,
+            selected,
+        };
+        self.update_badge(i)?;
+        self.draw_status()
+    }
+}
+
+/*1行分(128x8)の画像に文字を描画する*/
+fn render_row(font: &Font, s: &str) -> GrayImage {
+    let mut img = GrayImage::new(128, 8);
+    draw_text_mut(
+        &mut img,
+        Luma([255]),
+        0,
+        0,
+        Scale { x: 8.0, y: 8.0 },
+        font,
+        s,
+    );
+    img
+}
+
+/*行を上から順に並べた画面を生成する 画面に収まらない行は描画しない*/
+fn render_lines(font: &Font, lines: &[String]) -> GrayImage {
+    let mut img = GrayImage::new(128, 64);
+    for (i, s) in lines.iter().take(8).enumerate() {
+        draw_text_mut(
+            &mut img,
+            Luma([255]),
+            0,
+            (i * 8) as u32,
+            Scale { x: 8.0, y: 8.0 },
+            font,
+            s,
+        );
+    }
+    img
+}
+
+/*画面の中央にメッセージを表示する画像を生成する 改行で区切った行はそれぞれ中央に揃える*/
+fn render_message(font: &Font, s: &str) -> GrayImage {
+    let mut img = GrayImage::new(128, 64);
+    let lines = s.lines().take(8).collect::<Vec<_>>();
+    let top = (64 - 8 * lines.len() as u32) / 2;
+    for (i, line) in lines.iter().enumerate() {
+        let row = render_row(font, line);
+        let x = (128 - text_width(font, line).min(128)) / 2;
+        overlay(&mut img, &row, x, top + (i * 8) as u32);
+    }
+    img
+}
+
+/*フィードを読み込めなかったことと、その理由の要約を表示する画像を生成する*/
+fn render_fetch_error(font: &Font, e: &fetch::Error) -> GrayImage {
+    let reason = match &e.kind {
+        ErrorKind::Timeout => "応答がありません".to_string(),
+        ErrorKind::Dns(_) => "名前を解決できません".to_string(),
+        ErrorKind::Tls(_) => "TLSで接続できません".to_string(),
+        ErrorKind::Connect(_) => "接続できません".to_string(),
+        ErrorKind::Status(status) => format!("HTTPエラー {}", status.as_u16()),
+        ErrorKind::TooLarge(_) => "サイズが大きすぎます".to_string(),
+        ErrorKind::Request(_) | ErrorKind::Recorded(_) => "通信に失敗しました".to_string(),
+        ErrorKind::Parse(error) => match error {
+            feed::Error::Parse {
+                position: Some((line, column)),
+                ..
+            } => format!("{}行{}列目が不正です", line, column),
+            feed::Error::Parse { position: None, .. } => "内容が不正です".to_string(),
+            feed::Error::UnknownFormat(_) => "対応していない形式です".to_string(),
+        },
+    };
+    render_message(font, &format!("フィードを読み込めません\n\n{}", reason))
+}
+
+/*画像を取得してOLED用に変換する*/
+async fn fetch_image(fetcher: &Fetcher, url: &str) -> Result<GrayImage, Box<dyn Error>> {
+    let bytes = fetcher.download(url).await?;
+    render_image(&bytes).map_err(|e| format!("{}: {}", url, e).into())
+}
+
+/*
+ * JPEGやPNGなどの画像を128x64に収まるよう縮小して中央に置き、誤差拡散で2値化する
+ * 画素数が上限を超える画像はデコードせずにエラーにする
+ */
+fn render_image(bytes: &[u8]) -> Result<GrayImage, ImageError> {
+    let reader = || Reader::new(Cursor::new(bytes)).with_guessed_format();
+    let (width, height) = reader()?.into_dimensions()?;
+    if u64::from(width) * u64::from(height) > MAX_IMAGE_PIXELS {
+        return Err(ImageError::Limits(LimitError::from_kind(
+            LimitErrorKind::DimensionError,
+        )));
+    }
+    let img = reader()?
+        .decode()?
+        .resize(128, 64, FilterType::Triangle)
+        .to_luma8();
+    let mut page = GrayImage::new(128, 64);
+    overlay(
+        &mut page,
+        &img,
+        (128 - img.width()) / 2,
+        (64 - img.height()) / 2,
+    );
+    dither(&mut page, &BiLevel);
+    Ok(page)
+}
+
+/*
+ * 文字列をQRコードにして画面の中央に描画する
+ * 誤り訂正のレベルを最も低くしてなるべく小さい型番に収め、画面の高さに収まる整数倍で拡大する
+ * 周りの余白は画面の高さに収めるため仕様の4モジュールより狭い2モジュールにする
+ * 収まらなければその旨を表示する
+ */
+fn render_qr(font: &Font, s: &str) -> GrayImage {
+    const QUIET_ZONE: u32 = 2;
+    //余白を2モジュールにすると収まらない長さでも、1モジュールまで詰めて表示する
+    let (code, quiet_zone) = match QrCode::with_error_correction_level(s, EcLevel::L) {
+        Ok(code) if code.width() as u32 + 2 <= 64 => {
+            let quiet_zone = ((64 - code.width() as u32) / 2).min(QUIET_ZONE);
+            (code, quiet_zone)
+        }
+        _ => return render_message(font, "リンクが長すぎて\nQRコードにできません"),
+    };
+    let width = code.width() as u32;
+    let scale = 64 / (width + quiet_zone * 2);
+    let size = (width + quiet_zone * 2) * scale;
+    let (left, top) = ((128 - size) / 2, (64 - size) / 2);
+    //OLEDは光る部分が明るいので、余白と明るいモジュールを点灯させる
+    let mut img = GrayImage::new(128, 64);
+    draw_filled_rect_mut(
+        &mut img,
+        Rect::at(left as i32, top as i32).of_size(size, size),
+        Luma([255]),
+    );
+    for (i, color) in code.to_colors().iter().enumerate() {
+        if *color == Color::Dark {
+            let x = left + (i as u32 % width + quiet_zone) * scale;
+            let y = top + (i as u32 / width + quiet_zone) * scale;
+            draw_filled_rect_mut(
+                &mut img,
+                Rect::at(x as i32, y as i32).of_size(scale, scale),
+                Luma([0]),
+            );
+        }
+    }
+    img
+}
+
+/*画面の隅に重ねる短い文字列を、文字の幅だけ反転して描画する*/
+fn render_label(font: &Font, s: &str) -> GrayImage {
+    let width = (text_width(font, s) + 1).min(128);
+    let mut img = image::imageops::crop(&mut render_row(font, s), 0, 0, width, 8).to_image();
+    invert(&mut img);
+    img
+}
+
+/*タイトルの行を描画し、右端に"5分前"のような日付を重ねる*/
+fn render_title_row(font: &Font, title: &str, age: Option<&str>) -> GrayImage {
+    let mut img = render_row(font, title);
+    if let Some(age) = age {
+        let width = text_width(font, age).min(128);
+        let x = 128 - width;
+        //タイトルと日付の間に1ドットの隙間を空ける
+        draw_filled_rect_mut(
+            &mut img,
+            Rect::at(x as i32 - 1, 0).of_size(width + 1, 8),
+            Luma([0]),
+        );
+        draw_text_mut(
+            &mut img,
+            Luma([255]),
+            x,
+            0,
+            Scale { x: 8.0, y: 8.0 },
+            font,
+            age,
+        );
+    }
+    img
+}
+
+/*start_iから8行分を並べ、selected行目を反転した画面を生成する*/
+fn render_page<R: Rows>(rows: &mut R, start_i: usize, selected: usize) -> GrayImage {
+    let end = rows.len().min(start_i + 8);
+    (start_i..end)
+        .enumerate()
+        .fold(GrayImage::new(128, 64), |mut img, (i, index)| {
+            let mut page = rows.row(index);
+            if i == selected {
+                invert(&mut page);
+            }
+            overlay(&mut img, &page, 0, (i * 8) as u32);
+            img
+        })
+}
+
+fn move_down<D: Display, R: Rows>(
+    display: &mut D,
+    rows: &mut R,
+    start_i: &mut usize,
+    selected: &mut usize,
+) -> Result<(), Box<dyn Error>> {
+    if *selected < 7 && *start_i + *selected + 1 < rows.len() {
+        //反転している箇所を下に移動するだけで良い場合
+        let i = *start_i + *selected; //現在選択中のインデックス算出
+        display.draw_image(&rows.row(i), 0, *selected as u8)?; //反転していた表示をもとの表示に戻す
+        *selected += 1;
+        let mut img = rows.row(i + 1); //移動先の画像取得
+        invert(&mut img); //画像を反転
+        display.draw_image(&img, 0, *selected as u8)?; //反転した画像を表示
+    } else if *selected == 7 && rows.len() > *start_i + 8 {
+        //一番下を選択している状態で下に移動する場合
+        *start_i += 1;
+        display.draw_image(&render_page(rows, *start_i, 7), 0, 0)?;
+    }
+    Ok(())
+}
+
+fn move_up<D: Display, R: Rows>(
+    display: &mut D,
+    rows: &mut R,
+    start_i: &mut usize,
+    selected: &mut usize,
+) -> Result<(), Box<dyn Error>> {
+    if *selected > 0 {
+        //反転している箇所を上に移動するだけで良い場合
+        let i = *start_i + *selected; //現在選択中のインデックス算出
+        display.draw_image(&rows.row(i), 0, *selected as u8)?; //反転していた表示をもとの表示に戻す
+        *selected -= 1;
+        let mut img = rows.row(i - 1); //移動先の画像取得
+        invert(&mut img); //画像を反転
+        display.draw_image(&img, 0, *selected as u8)?; //反転した画像を表示
+    } else if *start_i > 0 {
+        //一番上を選択している状態で上に移動する場合
+        *start_i -= 1;
+        display.draw_image(&render_page(rows, *start_i, 0), 0, 0)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::PubDate;
+    use crate::display::SimulatedDisplay;
+    use crate::fetch::Policy;
+    use crate::record::{Record, Replayer};
+    use std::time::Duration;
+
+    const FEEDS: [(&str, &str); 9] = [
+        ("主要", "https://example.com/0.xml"),
+        ("国内", "https://example.com/1.xml"),
+        ("国際", "https://example.com/2.xml"),
+        ("経済", "https://example.com/3.xml"),
+        ("エンタメ", "https://example.com/4.xml"),
+        ("スポーツ", "https://example.com/5.xml"),
+        ("IT", "https://example.com/6.xml"),
+        ("科学", "https://example.com/7.xml"),
+        ("地域", "https://example.com/8.xml"),
+    ];
+
+    fn font() -> Font<'static> {
+        let v = Vec::from(include_bytes!("font/misaki_gothic.ttf") as &[u8]);
+        Font::try_from_vec(v).unwrap()
+    }
+
+    fn title_item(title: &str) -> feed::Item {
+        feed::Item {
+            title: title.to_string(),
+            link: None,
+            guid: None,
+            pub_date: None,
+            description: None,
+            content: None,
+            author: None,
+            categories: Vec::new(),
+            image: None,
+        }
+    }
+
+    fn scheduler() -> Scheduler {
+        Scheduler::new(vec![Duration::from_secs(300); FEEDS.len()])
+    }
+
+    #[tokio::test]
+    async fn replay_category_scroll_test() {
+        let mut app = app();
+        /*一番下(ブックマーク)まで移動してスクロールし、ひとつ上に戻る*/
+        let mut replayer =
+            Replayer::new("0 100\n0 100\n0 100\n0 100\n0 100\n0 100\n0 100\n0 100\n0 100\n0 001\n")
+                .unwrap();
+        while let Some(Record::Gesture(gesture)) = replayer.event().await {
+            app.gesture(gesture).await.unwrap();
+        }
+        assert_eq!(app.state(), State::Category);
+        assert_eq!(app.selected_index(), 8);
+        assert_eq!(app.category_pane.start_i, 2);
+        assert_eq!(app.category_pane.selected, 6);
+
+        let mut expected = render_page(&mut app.category_pane.categories, 2, 6);
+        dither(&mut expected, &BiLevel);
+        assert_eq!(app.display().frame(), &expected);
+    }
+
+    #[tokio::test]
+    async fn ignore_back_on_category_test() {
+        let mut app = app();
+        app.gesture(Gesture::press([true, false, true]))
+            .await
+            .unwrap();
+        app.gesture(Gesture::press([false, false, true]))
+            .await
+            .unwrap();
+        assert_eq!(app.state(), State::Category);
+        assert_eq!(app.selected_index(), 0);
+    }
+
+    #[tokio::test]
+    async fn command_test() {
+        let mut app = app();
+        app.command(&Command::Action(Action::Down)).await.unwrap();
+        app.command(&Command::Action(Action::Down)).await.unwrap();
+        app.command(&Command::Action(Action::Up)).await.unwrap();
+        assert_eq!(app.screen(), "category 1");
+        app.command(&Command::Status).await.unwrap();
+        assert_eq!(app.screen(), "category 1");
+        assert!(app.command(&Command::Goto(10)).await.is_err());
+        assert_eq!(app.state(), State::Category);
+    }
+
+    /*テスト用のカテゴリを持ち、保存先のないApp*/
+    fn app() -> App<SimulatedDisplay> {
+        App::new(
+            SimulatedDisplay::new(None),
+            font(),
+            &FEEDS,
+            Keymap::default(),
+            scheduler(),
+            Fetcher::new(None),
+            None,
+        )
+        .unwrap()
+    }
+
+    /*urlから今取得したitemsを持つフィード*/
+    fn entry(url: &str, items: Vec<feed::Item>) -> Entry {
+        Entry {
+            url: url.to_string(),
+            fetched_at: Utc::now(),
+            validators: Default::default(),
+            feed: feed::Feed {
+                title: String::new(),
+                link: None,
+                description: None,
+                items,
+                warnings: Vec::new(),
+                schedule: Default::default(),
+            },
+        }
+    }
+
+    /*接続できないurlのカテゴリだけを持つApp*/
+    fn offline_app(cache: Option<Cache>) -> App<SimulatedDisplay> {
+        App::new(
+            SimulatedDisplay::new(None),
+            font(),
+            &[("主要", "http://127.0.0.1:1/rss.xml")],
+            Keymap::default(),
+            Scheduler::new(vec![Duration::from_secs(300)]),
+            Fetcher::with_policy(
+                None,
+                Policy {
+                    retries: 0,
+                    ..Policy::default()
+                },
+            ),
+            cache,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn fetch_error_test() {
+        //接続できないフィードを開いてもエラー画面を表示するだけで、戻ることができる
+        let mut app = offline_app(None);
+        app.command(&Command::Goto(0)).await.unwrap();
+        assert_eq!(app.screen(), "error 0");
+        app.gesture(Gesture::press([false, true, false]))
+            .await
+            .unwrap();
+        assert_eq!(app.screen(), "category 0");
+    }
+
+    #[tokio::test]
+    async fn replay_fetch_test() {
+        //記録の再生中は通信せず、記録した結果でタイトル一覧を開く
+        let mut app = offline_app(None);
+        app.replay_fetches();
+        app.record_fetches();
+        let feed = entry(
+            "http://127.0.0.1:1/rss.xml",
+            vec![title_item("記録した記事")],
+        )
+        .feed;
+        app.replay_fetched(0, Outcome::Modified(Box::new(feed), Default::default()));
+        app.command(&Command::Goto(0)).await.unwrap();
+        assert_eq!(app.screen(), "title 0 0");
+        assert_eq!(app.title_pane.titles.items[0].title, "記録した記事");
+        //記録中なら使った結果を取り出せる
+        let fetched = app.take_fetched();
+        assert!(matches!(fetched.as_slice(), [(0, Outcome::Modified(..))]));
+        assert!(app.take_fetched().is_empty());
+
+        //記録にない取得は保持しているものが変わっていないものとする
+        app.command(&Command::Action(Action::Refresh))
+            .await
+            .unwrap();
+        assert_eq!(app.screen(), "title 0 0");
+        assert!(matches!(
+            app.take_fetched().as_slice(),
+            [(0, Outcome::NotModified)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn offline_cache_test() {
+        //接続できなくても保存しておいたフィードをキャッシュの印付きで表示する
+        let dir = std::env::temp_dir().join(format!("rss-reader-app-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let item = title_item("保存した記事");
+        cache
+            .save(&Entry {
+                fetched_at: Utc::now() - chrono::Duration::hours(3),
+                ..entry("http://127.0.0.1:1/rss.xml", vec![item])
+            })
+            .unwrap();
+        let mut app = offline_app(Some(cache));
+        app.command(&Command::Goto(0)).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(app.screen(), "title 0 0");
+        assert_eq!(app.title_pane.titles.items[0].title, "保存した記事");
+
+        let marker = render_label(&font(), "キャッシュ 3時間前");
+        let x = 128 - marker.width();
+        for (mx, y, pixel) in marker.enumerate_pixels() {
+            assert_eq!(app.display().frame().get_pixel(x + mx, 56 + y), pixel);
+        }
+    }
+
+    #[tokio::test]
+    async fn unread_badge_test() {
+        let mut app = app();
+        app.feeds[2] = Some(entry(
+            FEEDS[2].1,
+            vec![title_item("a"), title_item("b"), title_item("c")],
+        ));
+        //一度も開いていないカテゴリはすべて新着として数え、表示中の行も描き直す
+        app.update_badge(2).unwrap();
+        let expected = render_title_row(&font(), "国際", Some("新着3"));
+        assert_eq!(app.category_pane.categories[2], expected);
+        let mut frame = app.display().frame().clone();
+        let row = image::imageops::crop(&mut frame, 0, 16, 128, 8).to_image();
+        let mut expected_frame = expected.clone();
+        dither(&mut expected_frame, &BiLevel);
+        assert_eq!(row, expected_frame);
+
+        //タイトル一覧を表示したら新着はなくなる
+        app.show_titles(2, 0, 0).unwrap();
+        assert_eq!(app.unread_count(2), 0);
+        assert_eq!(app.category_pane.categories[2], render_row(&font(), "国際"));
+
+        app.feeds[2]
+            .as_mut()
+            .unwrap()
+            .feed
+            .items
+            .push(title_item("d"));
+        assert_eq!(app.unread_count(2), 1);
+    }
+
+    #[tokio::test]
+    async fn prefetch_test() {
+        let mut app = app();
+        let requests = app.start_prefetch().unwrap();
+        assert_eq!(requests.len(), FEEDS.len());
+        assert_eq!(requests[3].url, FEEDS[3].1);
+        //結果が届くまでは定期的な取得をしない
+        assert!(app.scheduler.due(Utc::now()).is_empty());
+
+        for (n, request) in requests.iter().enumerate() {
+            let label = render_label(&font(), &format!("取得中 {}/{}", n, FEEDS.len()));
+            let x = 128 - label.width();
+            for (lx, y, pixel) in label.enumerate_pixels() {
+                assert_eq!(app.display().frame().get_pixel(x + lx, 56 + y), pixel);
+            }
+            let feed = entry(request.url.as_str(), vec![title_item("a")]).feed;
+            let result = Ok(Fetched::Modified(Box::new(feed), Default::default()));
+            app.prefetched(request.index, Utc::now(), result).unwrap();
+        }
+        //すべて届いたら進み具合の表示を消し、新着の件数を添えたカテゴリ一覧にする
+        assert!(app.progress.is_none());
+        let mut expected = render_page(&mut app.category_pane.categories, 0, 0);
+        dither(&mut expected, &BiLevel);
+        assert_eq!(app.display().frame(), &expected);
+        assert_eq!(
+            app.category_pane.categories[8],
+            render_title_row(&font(), "地域", Some("新着1"))
+        );
+        app.command(&Command::Goto(8)).await.unwrap();
+        assert_eq!(app.screen(), "title 8 0");
+    }
+
+    #[tokio::test]
+    async fn late_prefetch_test() {
+        //先読みより後に始めた取得の結果を反映していれば、遅れて届いた先読みの結果で上書きしない
+        let mut app = app();
+        let requests = app.start_prefetch().unwrap();
+        let started = Utc::now();
+        let newer = entry(FEEDS[0].1, vec![title_item("新しい記事")]).feed;
+        let result = Ok(Fetched::Modified(Box::new(newer), Default::default()));
+        app.refreshed(0, started + chrono::Duration::seconds(1), result)
+            .unwrap();
+
+        let older = entry(FEEDS[0].1, vec![title_item("古い記事")]).feed;
+        let result = Ok(Fetched::Modified(Box::new(older), Default::default()));
+        app.prefetched(requests[0].index, started, result).unwrap();
+        let items = &app.feeds[0].as_ref().unwrap().feed.items;
+        assert_eq!(items[0].title, "新しい記事");
+        assert_eq!(app.progress, Some((1, FEEDS.len())));
+    }
+
+    #[tokio::test]
+    async fn prefetch_cache_test() {
+        //起動し直しても保存しておいた記事は新着として数えず、その後に増えた記事だけを数える
+        let dir = std::env::temp_dir().join(format!("rss-reader-seen-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let url = "http://127.0.0.1:1/rss.xml";
+        cache.save(&entry(url, vec![title_item("a")])).unwrap();
+        let mut app = offline_app(Some(cache));
+        app.start_prefetch().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(app.unread_count(0), 0);
+
+        let feed = entry(url, vec![title_item("b"), title_item("a")]).feed;
+        let result = Ok(Fetched::Modified(Box::new(feed), Default::default()));
+        app.prefetched(0, Utc::now(), result).unwrap();
+        assert_eq!(app.unread_count(0), 1);
+    }
+
+    #[tokio::test]
+    async fn refresh_test() {
+        let mut app = app();
+        //取得を頼んだカテゴリは結果が届くまで再び頼まない
+        let requests = app.tick();
+        assert_eq!(requests.len(), FEEDS.len());
+        assert!(app.tick().is_empty());
+
+        //表示中のタイトル一覧は結果が届いたら描き直す
+        app.feeds[0] = Some(entry(FEEDS[0].1, vec![title_item("a")]));
+        app.show_titles(0, 0, 0).unwrap();
+        let feed = entry(FEEDS[0].1, vec![title_item("b"), title_item("a")]).feed;
+        let result = Ok(Fetched::Modified(Box::new(feed), Default::default()));
+        app.refreshed(0, Utc::now(), result).unwrap();
+        assert_eq!(app.screen(), "title 0 0");
+        assert_eq!(app.title_pane.titles.items[0].title, "b");
+    }
+
+    #[tokio::test]
+    async fn read_state_test() {
+        let dir = std::env::temp_dir().join(format!("rss-reader-read-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let mut items = vec![title_item("a"), title_item("b")];
+        items[0].guid = Some("a".to_string());
+        cache
+            .save(&entry("http://127.0.0.1:1/rss.xml", items))
+            .unwrap();
+        let mut app = offline_app(Some(cache));
+        app.command(&Command::Goto(0)).await.unwrap();
+        assert_eq!(app.title_pane.titles.unread, [true, true]);
+
+        //本文を開いた記事は既読になり、戻ると印が消えている
+        app.action(Action::Select).await.unwrap();
+        app.action(Action::Back).await.unwrap();
+        assert_eq!(app.title_pane.titles.unread, [false, true]);
+        let mut row = app.title_pane.titles.row(0);
+        assert_eq!(row, render_row(&font(), "a"));
+        invert(&mut row);
+        dither(&mut row, &BiLevel);
+        let mut frame = app.display().frame().clone();
+        assert_eq!(
+            image::imageops::crop(&mut frame, 0, 0, 128, 8).to_image(),
+            row
+        );
+
+        //未読に戻す、すべて既読にする
+        app.action(Action::Unread).await.unwrap();
+        assert_eq!(app.title_pane.titles.unread, [true, true]);
+        assert_eq!(app.title_pane.titles.row(0), render_row(&font(), "●a"));
+        app.action(Action::ReadAll).await.unwrap();
+        assert_eq!(app.title_pane.titles.unread, [false, false]);
+
+        //既読の記録は起動し直しても残っている
+        let mut app = offline_app(Some(Cache::new(&dir)));
+        app.command(&Command::Goto(0)).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(app.title_pane.titles.unread, [false, false]);
+    }
+
+    #[tokio::test]
+    async fn bookmark_test() {
+        let mut app = app();
+        //ブックマークがなければその旨を表示して戻れる
+        app.command(&Command::Goto(9)).await.unwrap();
+        assert_eq!(app.screen(), "error 9");
+        app.action(Action::Back).await.unwrap();
+
+        app.feeds[0] = Some(entry(FEEDS[0].1, vec![title_item("a"), title_item("b")]));
+        app.category_pane.start_i = 0;
+        app.category_pane.selected = 0;
+        app.show_titles(0, 0, 1).unwrap();
+        app.action(Action::Bookmark).await.unwrap();
+        let label = render_label(&font(), "ブックマークしました");
+        let x = 128 - label.width();
+        for (lx, y, pixel) in label.enumerate_pixels() {
+            assert_eq!(app.display().frame().get_pixel(x + lx, 56 + y), pixel);
+        }
+
+        //元のフィードから消えてもブックマークには残る
+        app.feeds[0].as_mut().unwrap().feed.items.clear();
+        app.command(&Command::Goto(9)).await.unwrap();
+        assert_eq!(app.screen(), "title 9 0");
+        assert_eq!(app.title_pane.titles.items[0].title, "b");
+
+        //ブックマークの一覧で外すと一覧から消える
+        app.action(Action::Bookmark).await.unwrap();
+        assert_eq!(app.screen(), "error 9");
+
+        //本文から外したときもタイトル一覧に戻って一覧から消える
+        app.bookmarks.toggle(&title_item("a"));
+        app.bookmarks.toggle(&title_item("b"));
+        app.command(&Command::Goto(9)).await.unwrap();
+        app.action(Action::Select).await.unwrap();
+        assert_eq!(app.screen(), "overview 9 0");
+        app.action(Action::Bookmark).await.unwrap();
+        assert_eq!(app.screen(), "title 9 0");
+        assert_eq!(app.title_pane.titles.len(), 1);
+        assert_eq!(app.title_pane.titles.items[0].title, "a");
+    }
+
+    #[tokio::test]
+    async fn empty_feed_test() {
+        //記事のないフィードではその旨を表示し、記事を選ぶ操作をしても何も起きない
+        let mut app = app();
+        app.feeds[0] = Some(entry(FEEDS[0].1, Vec::new()));
+        app.show_titles(0, 0, 0).unwrap();
+        assert_eq!(app.screen(), "error 0");
+        assert_eq!(
+            app.display().frame(),
+            &render_message(&font(), "記事はありません")
+        );
+        for action in [Action::Select, Action::Bookmark, Action::Unread, Action::Qr] {
+            app.action(action).await.unwrap();
+            assert_eq!(app.screen(), "error 0");
+        }
+        app.action(Action::Back).await.unwrap();
+        assert_eq!(app.screen(), "category 0");
+    }
+
+    #[tokio::test]
+    async fn overview_pages_test() {
+        let mut app = app();
+        let mut item = title_item("長い記事");
+        item.description = Some(
+            (1..=20)
+                .map(|i| format!("{}行目", i))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        app.feeds[0] = Some(entry(FEEDS[0].1, vec![title_item("a"), item]));
+        app.show_titles(0, 0, 1).unwrap();
+        app.gesture(Gesture::press([false, true, false]))
+            .await
+            .unwrap();
+        assert_eq!(app.overview_pane.pages(), 3);
+        let frame = |app: &App<SimulatedDisplay>, page: usize| {
+            let start = page * 7;
+            let lines = (start + 1..=(start + 7).min(20))
+                .map(|i| format!("{}行目", i))
+                .collect::<Vec<_>>();
+            let mut expected = render_lines(&font(), &lines);
+            let label = render_label(&font(), &format!("{}/3", page + 1));
+            overlay(&mut expected, &label, 128 - label.width(), 56);
+            dither(&mut expected, &BiLevel);
+            assert_eq!(app.display().frame(), &expected, "page {}", page);
+        };
+        frame(&app, 0);
+
+        //最後のページより先には進まない
+        for _ in 0..3 {
+            app.gesture(Gesture::press([true, false, false]))
+                .await
+                .unwrap();
+        }
+        frame(&app, 2);
+        app.gesture(Gesture::press([false, false, true]))
+            .await
+            .unwrap();
+        frame(&app, 1);
+
+        //戻ると同じタイトルを選択している
+        app.gesture(Gesture::press([true, false, true]))
+            .await
+            .unwrap();
+        assert_eq!(app.screen(), "title 0 1");
+    }
+
+    #[test]
+    fn render_title_row_test() {
+        let font = font();
+        assert_eq!(text_width(&font, "5分前"), 20);
+
+        let img = render_title_row(&font, &"あ".repeat(16), Some("5分前"));
+        let age = render_row(&font, "5分前");
+        for y in 0..8 {
+            //隙間の列は消えている
+            assert_eq!(img.get_pixel(107, y), &Luma([0]));
+            for x in 0..20 {
+                assert_eq!(img.get_pixel(108 + x, y), age.get_pixel(x, y));
+            }
+        }
+        assert_eq!(
+            render_title_row(&font, "タイトル", None),
+            render_row(&font, "タイトル")
+        );
+    }
+
+    #[test]
+    fn render_qr_test() {
+        //短いurlは2倍に拡大し、点灯と消灯だけで描画する
+        let url = "https://example.com/1";
+        let img = render_qr(&font(), url);
+        let code = QrCode::with_error_correction_level(url, EcLevel::L).unwrap();
+        assert_eq!(code.width(), 25);
+        let (left, top) = ((128 - 58) / 2, (64 - 58) / 2);
+        for (i, color) in code.to_colors().iter().enumerate() {
+            let (x, y) = (i as u32 % 25, i as u32 / 25);
+            let expected = if *color == Color::Dark { 0 } else { 255 };
+            for (dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let pixel = img.get_pixel(left + (x + 2) * 2 + dx, top + (y + 2) * 2 + dy);
+                assert_eq!(pixel, &Luma([expected]), "({}, {})", x, y);
+            }
+        }
+        assert_eq!(img.get_pixel(left, top), &Luma([255]));
+        assert_eq!(img.get_pixel(left - 1, top), &Luma([0]));
+        let mut dithered = img.clone();
+        dither(&mut dithered, &BiLevel);
+        assert_eq!(dithered, img);
+
+        //余白を詰めれば収まるurlは余白を1モジュールにする
+        let url = format!("https://example.com/{}", "a".repeat(280));
+        let code = QrCode::with_error_correction_level(&url, EcLevel::L).unwrap();
+        assert_eq!(code.width(), 61);
+        let img = render_qr(&font(), &url);
+        let left = (128 - 63) / 2;
+        assert_eq!(img.get_pixel(left, 0), &Luma([255]));
+        assert_eq!(img.get_pixel(left - 1, 0), &Luma([0]));
+        assert_eq!(img.get_pixel(left + 62, 62), &Luma([255]));
+
+        //それでも収まらない長いurlはその旨を表示する
+        let url = format!("https://example.com/{}", "a".repeat(400));
+        assert_eq!(
+            render_qr(&font(), &url),
+            render_message(&font(), "リンクが長すぎて\nQRコードにできません")
+        );
+    }
+
+    #[test]
+    fn render_image_test() {
+        //横長の画像は上下に余白を空けて表示する
+        let mut png = Vec::new();
+        image::DynamicImage::ImageLuma8(GrayImage::from_pixel(256, 64, Luma([255])))
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        let img = render_image(&png).unwrap();
+        assert_eq!(img.dimensions(), (128, 64));
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let expected = if (16..48).contains(&y) { 255 } else { 0 };
+            assert_eq!(pixel, &Luma([expected]), "({}, {})", x, y);
+        }
+
+        assert!(render_image(b"not an image").is_err());
+        //画素数が上限を超える画像はデコードしない
+        assert!(matches!(
+            render_image(b"P5\n20000 20000\n255\n"),
+            Err(ImageError::Limits(_))
+        ));
+    }
+
+    #[test]
+    fn lazy_title_rows_test() {
+        let items = (0..500)
+            .map(|i| title_item(&format!("{}件目", i)))
+            .collect::<Vec<_>>();
+        let mut titles = TitleRows::new(font(), items, vec![false; 500]);
+        let mut display = SimulatedDisplay::new(None);
+        let (mut start_i, mut selected) = (0, 0);
+        display
+            .draw_image(&render_page(&mut titles, start_i, selected), 0, 0)
+            .unwrap();
+        for _ in 0..9 {
+            move_down(&mut display, &mut titles, &mut start_i, &mut selected).unwrap();
+        }
+        assert_eq!((start_i, selected), (2, 7));
+        //表示したことのある10行だけが描画されている
+        let rendered = titles.rendered.iter().filter(|row| row.is_some()).count();
+        assert_eq!(rendered, 10);
+        assert_eq!(titles.row(9), render_row(&font(), "9件目"));
+    }
+
+    #[test]
+    fn title_row_age_test() {
+        let mut item = title_item("記事");
+        let published = Utc::now() - chrono::Duration::minutes(5);
+        item.pub_date = Some(PubDate::parse(&published.to_rfc3339()));
+        let mut titles = TitleRows::new(font(), vec![item], vec![false]);
+        assert_eq!(
+            titles.row(0),
+            render_title_row(&font(), "記事", Some("5分前"))
+        );
+        //時間が経って日付の表示が変われば描き直す
+        let published = Utc::now() - chrono::Duration::hours(2);
+        titles.items[0].pub_date = Some(PubDate::parse(&published.to_rfc3339()));
+        assert_eq!(
+            titles.row(0),
+            render_title_row(&font(), "記事", Some("2時間前"))
+        );
+    }
+}
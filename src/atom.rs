@@ -0,0 +1,441 @@
+use crate::date::PubDate;
+use crate::feed::{self, Feed, Schedule, Warning};
+use crate::html;
+use std::mem;
+use xml::attribute::OwnedAttribute;
+use xml::name::OwnedName;
+use xml::reader::{Error, ParserConfig, XmlEvent};
+
+const ATOM_NS: &str = "http://www.w3.org/2005/Atom";
+
+/*
+ * Atomのフィード
+ * RSS 2.0と同じく文書を先頭から順に読みながら組み立て、知らない要素は読み飛ばす
+ * type="html"とtype="xhtml"の要素はタグを取り除いた文字列にし、type="text"の要素はそのまま使う
+ * xhtmlの子要素は一度タグとして残し、HTMLと同じように変換する
+ */
+#[derive(Default)]
+pub struct Atom {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub updated: Option<String>,
+    pub links: Vec<Link>,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Default)]
+pub struct Entry {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub updated: Option<String>,
+    pub published: Option<String>,
+    pub summary: Option<String>,
+    pub content: Option<String>,
+    pub links: Vec<Link>,
+    pub authors: Vec<Person>,
+    pub categories: Vec<Category>,
+}
+
+#[derive(Default)]
+pub struct Person {
+    pub name: Option<String>,
+}
+
+pub struct Category {
+    pub term: String,
+    pub label: Option<String>,
+}
+
+pub struct Link {
+    pub href: String,
+    pub rel: Option<String>,
+}
+
+/*読み込み中の文字列を持つ要素*/
+struct Field {
+    depth: usize,
+    kind: Option<String>, //type属性
+}
+
+impl Atom {
+    pub fn new(s: &str) -> Result<Self, Error> {
+        Self::with_limit(s, None)
+    }
+
+    /*entryをmax_items件読んだところで読むのをやめる*/
+    pub fn with_limit(s: &str, max_items: Option<usize>) -> Result<Self, Error> {
+        //xhtmlの要素の間の空白も文字として残す
+        let reader = ParserConfig::new()
+            .whitespace_to_characters(true)
+            .cdata_to_characters(true)
+            .coalesce_characters(true)
+            .ignore_comments(true)
+            .create_reader(s.as_bytes());
+
+        let mut atom = Atom::default();
+        let mut stack = Vec::new(); //開いている要素(<feed>が先頭)
+        let mut entry = None; //読み込み中のentry
+        let mut field = None; //文字列を集めている要素
+        let mut text = String::new();
+        for event in reader {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    stack.push(name);
+                    let name = &stack[stack.len() - 1];
+                    if stack.len() < 2 || !is_atom(&stack[0], "feed") {
+                        continue;
+                    }
+                    if let Some(Field { kind, .. }) = &field {
+                        //xhtmlの子要素はタグとして残す
+                        if kind.as_deref() == Some("xhtml") {
+                            text.push_str(&format!("<{}>", name.local_name));
+                        }
+                        continue;
+                    }
+                    match (stack.len(), &mut entry) {
+                        (2, None) if is_atom(name, "entry") => {
+                            entry = Some(Entry::default());
+                            continue;
+                        }
+                        (2, None) => {
+                            if let Some(link) = link(name, &attributes) {
+                                atom.links.push(link);
+                            }
+                        }
+                        (3, Some(entry)) => {
+                            start_entry_element(entry, name, &attributes);
+                            //<author>は子要素の<name>を読む
+                            if is_atom(name, "author") {
+                                continue;
+                            }
+                        }
+                        (4, Some(_)) if is_atom(&stack[2], "author") => (),
+                        _ => continue,
+                    }
+                    text.clear();
+                    field = Some(Field {
+                        depth: stack.len(),
+                        kind: attribute(&attributes, "type"),
+                    });
+                }
+                XmlEvent::Characters(s) => match &field {
+                    Some(Field { kind, .. }) if kind.as_deref() == Some("xhtml") => {
+                        text.push_str(&escape(&s))
+                    }
+                    Some(_) => text.push_str(&s),
+                    None => (),
+                },
+                XmlEvent::EndElement { .. } => {
+                    let name = &stack[stack.len() - 1];
+                    match &field {
+                        Some(Field { depth, kind }) if *depth == stack.len() => {
+                            let value = mem::take(&mut text);
+                            let value = match kind.as_deref() {
+                                Some("html") | Some("xhtml") => html::to_text(&value),
+                                _ => value.trim().to_string(),
+                            };
+                            match &mut entry {
+                                Some(entry) if stack.len() == 4 => {
+                                    if is_atom(name, "name") {
+                                        if let Some(author) = entry.authors.last_mut() {
+                                            author.name = Some(value);
+                                        }
+                                    }
+                                }
+                                Some(entry) => set_entry_field(entry, name, value),
+                                None => set_feed_field(&mut atom, name, value),
+                            }
+                            field = None;
+                        }
+                        Some(Field { kind, .. }) => {
+                            if kind.as_deref() == Some("xhtml") {
+                                text.push_str(&format!("</{}>", name.local_name));
+                            }
+                        }
+                        None => {
+                            if stack.len() == 2 {
+                                if let Some(entry) = entry.take() {
+                                    atom.entries.push(entry);
+                                }
+                            }
+                        }
+                    }
+                    stack.pop();
+                    if matches!(max_items, Some(max_items) if atom.entries.len() >= max_items) {
+                        break;
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(atom)
+    }
+
+    /*仕様に沿っていない箇所を集める*/
+    pub fn warnings(&self) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        if self.title.is_empty() {
+            warnings.push(Warning::channel("missing <title>"));
+        }
+        for (i, entry) in self.entries.iter().enumerate() {
+            if entry.title.is_none() {
+                warnings.push(Warning::item(i, "missing <title>"));
+            }
+            if entry.id.is_none() {
+                warnings.push(Warning::item(i, "missing <id>"));
+            }
+        }
+        warnings
+    }
+}
+
+/*Atomの名前空間の要素か 名前空間を宣言していないフィードも受け付ける*/
+fn is_atom(name: &OwnedName, local_name: &str) -> bool {
+    matches!(name.namespace.as_deref(), None | Some(ATOM_NS)) && name.local_name == local_name
+}
+
+fn attribute(attributes: &[OwnedAttribute], local_name: &str) -> Option<String> {
+    attributes
+        .iter()
+        .find(|attr| attr.name.namespace.is_none() && attr.name.local_name == local_name)
+        .map(|attr| attr.value.clone())
+}
+
+/*hrefのある<link>*/
+fn link(name: &OwnedName, attributes: &[OwnedAttribute]) -> Option<Link> {
+    if !is_atom(name, "link") {
+        return None;
+    }
+    Some(Link {
+        href: attribute(attributes, "href")?,
+        rel: attribute(attributes, "rel"),
+    })
+}
+
+/*属性だけを持つentryの子要素と、子要素を持つ<author>*/
+fn start_entry_element(entry: &mut Entry, name: &OwnedName, attributes: &[OwnedAttribute]) {
+    if let Some(link) = link(name, attributes) {
+        entry.links.push(link);
+    } else if is_atom(name, "category") {
+        if let Some(term) = attribute(attributes, "term") {
+            entry.categories.push(Category {
+                term,
+                label: attribute(attributes, "label"),
+            });
+        }
+    } else if is_atom(name, "author") {
+        entry.authors.push(Person::default());
+    }
+}
+
+/*xhtmlの文字を、後でHTMLとして変換したときに元に戻るようエスケープする*/
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn set_feed_field(atom: &mut Atom, name: &OwnedName, value: String) {
+    if !matches!(name.namespace.as_deref(), None | Some(ATOM_NS)) {
+        return;
+    }
+    match name.local_name.as_str() {
+        "title" => atom.title = value,
+        "subtitle" => atom.subtitle = Some(value),
+        "updated" => atom.updated = Some(value),
+        _ => (),
+    }
+}
+
+fn set_entry_field(entry: &mut Entry, name: &OwnedName, value: String) {
+    if !matches!(name.namespace.as_deref(), None | Some(ATOM_NS)) {
+        return;
+    }
+    match name.local_name.as_str() {
+        "id" => entry.id = Some(value),
+        "title" => entry.title = Some(value),
+        "updated" => entry.updated = Some(value),
+        "published" => entry.published = Some(value),
+        "summary" => entry.summary = Some(value),
+        "content" => entry.content = Some(value),
+        _ => (),
+    }
+}
+
+/*rel="alternate"(省略時も同じ)のリンクを優先し、なければ最初のリンクを返す*/
+fn alternate(links: Vec<Link>) -> Option<String> {
+    let i = links
+        .iter()
+        .position(|link| link.rel.as_deref().unwrap_or("alternate") == "alternate")
+        .unwrap_or(0);
+    links.into_iter().nth(i).map(|link| link.href)
+}
+
+/*複数の著者は"、"でつなぐ*/
+fn join_names(people: Vec<Person>) -> Option<String> {
+    let names = people
+        .into_iter()
+        .filter_map(|person| person.name)
+        .collect::<Vec<_>>();
+    Some(names.join("、")).filter(|names| !names.is_empty())
+}
+
+impl From<Atom> for Feed {
+    fn from(atom: Atom) -> Self {
+        let warnings = atom.warnings();
+        Feed {
+            title: atom.title,
+            link: alternate(atom.links),
+            description: atom.subtitle,
+            items: atom
+                .entries
+                .into_iter()
+                .map(|entry| feed::Item {
+                    title: entry.title.unwrap_or_default(),
+                    link: alternate(entry.links),
+                    guid: entry.id,
+                    pub_date: entry
+                        .published
+                        .or(entry.updated)
+                        .as_deref()
+                        .map(PubDate::parse),
+                    description: entry.summary,
+                    content: entry.content,
+                    author: join_names(entry.authors),
+                    categories: entry
+                        .categories
+                        .into_iter()
+                        .map(|category| category.label.unwrap_or(category.term))
+                        .collect(),
+                    image: None,
+                })
+                .collect(),
+            warnings,
+            schedule: Schedule::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn make_atom_test() {
+        let s = r#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+                <title>フィードのタイトルです</title>
+                <link rel="self" href="https://example.com/feed.atom"/>
+                <updated>2021-08-12T12:03:56Z</updated>
+                <link href="https://example.com/"/>
+                <id>urn:uuid:60a76c80-d399-11d9-b93c-0003939e0af6</id>
+                <entry>
+                    <title type="text">エントリーのタイトルです</title>
+                    <link rel="alternate" href="https://example.com/1"/>
+                    <link rel="related" href="https://example.com/related"/>
+                    <id>urn:uuid:1225c695-cfb8-4ebb-aaaa-80da344efa6a</id>
+                    <updated>2021-08-12T11:37:05Z</updated>
+                    <summary>エントリーの要約です</summary>
+                    <content type="html">&lt;p&gt;エントリーの本文です&lt;/p&gt;</content>
+                </entry>
+                <entry>
+                    <title>二件目</title>
+                    <link rel="related" href="https://example.com/related"/>
+                    <published>2021-08-11T09:00:00+09:00</published>
+                    <updated>2021-08-12T09:00:00+09:00</updated>
+                </entry>
+            </feed>
+        "#;
+        let feed = Feed::from(Atom::new(s).unwrap());
+        assert_eq!(feed.title, "フィードのタイトルです");
+        assert_eq!(feed.link.unwrap(), "https://example.com/");
+        assert_eq!(feed.description, None);
+        assert_eq!(feed.warnings, [Warning::item(1, "missing <id>")]);
+
+        let items = &feed.items;
+        assert_eq!(items.len(), 2);
+        let item = items.first().unwrap();
+        assert_eq!(item.title, "エントリーのタイトルです");
+        assert_eq!(item.link.as_ref().unwrap(), "https://example.com/1");
+        assert_eq!(item.pub_date.as_ref().unwrap().raw, "2021-08-12T11:37:05Z");
+        assert_eq!(item.description.as_ref().unwrap(), "エントリーの要約です");
+        assert_eq!(item.content.as_ref().unwrap(), "エントリーの本文です");
+
+        let item = items.get(1).unwrap();
+        assert_eq!(item.link.as_ref().unwrap(), "https://example.com/related");
+        assert_eq!(
+            item.pub_date.as_ref().unwrap().raw,
+            "2021-08-11T09:00:00+09:00"
+        );
+        assert_eq!(item.description, None);
+    }
+
+    #[test]
+    fn xhtml_test() {
+        //xhtmlの本文や子要素を持つ著者があってもフィード全体を読み込める
+        let s = r#"
+            <feed xmlns="http://www.w3.org/2005/Atom">
+                <title type="xhtml"><div xmlns="http://www.w3.org/1999/xhtml"><b>太字</b>の題</div></title>
+                <entry>
+                    <id>tag:example.com,2021:1</id>
+                    <title>一件目</title>
+                    <author><name>著者A</name><uri>https://example.com/a</uri></author>
+                    <author><name>著者B</name></author>
+                    <category term="rust" label="Rust"/>
+                    <category term="embedded"/>
+                    <content type="xhtml">
+                        <div xmlns="http://www.w3.org/1999/xhtml">
+                            <p>一段落目 <em>強調</em> です。</p>
+                            <p>1 &lt; 2 &amp;amp;</p>
+                        </div>
+                    </content>
+                </entry>
+                <entry><id>2</id><title>二件目</title></entry>
+                <entry><id>3</id><title>三件目</title></entry>
+            </feed>
+        "#;
+        let feed = Feed::from(Atom::new(s).unwrap());
+        assert_eq!(feed.title, "太字の題");
+        assert!(feed.warnings.is_empty());
+        let item = &feed.items[0];
+        assert_eq!(
+            item.content.as_deref(),
+            Some("一段落目 強調 です。\n1 < 2 &amp;")
+        );
+        assert_eq!(item.author.as_deref(), Some("著者A、著者B"));
+        assert_eq!(item.categories, ["Rust", "embedded"]);
+
+        //entryをmax_items件読んだところでやめる
+        let atom = Atom::with_limit(s, Some(2)).unwrap();
+        assert_eq!(atom.entries.len(), 2);
+        assert_eq!(atom.entries[1].title.as_deref(), Some("二件目"));
+    }
+
+    #[test]
+    fn text_type_test() {
+        //type="text"(省略時も同じ)の要約や本文はタグのように見えてもそのまま使う
+        let s = r#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+                <title>題</title>
+                <entry>
+                    <id>1</id>
+                    <title>一件目</title>
+                    <summary type="text">&lt;b&gt;は太字のタグ &amp;amp;</summary>
+                    <content>1 &lt; 2</content>
+                </entry>
+                <entry>
+                    <id>2</id>
+                    <title>二件目</title>
+                    <summary type="html">&lt;b&gt;太字&lt;/b&gt; &amp;amp;</summary>
+                </entry>
+            </feed>
+        "#;
+        let feed = Feed::from(Atom::new(s).unwrap());
+        let item = &feed.items[0];
+        assert_eq!(item.description.as_deref(), Some("<b>は太字のタグ &amp;"));
+        assert_eq!(item.content.as_deref(), Some("1 < 2"));
+        let item = &feed.items[1];
+        assert_eq!(item.description.as_deref(), Some("太字 &"));
+    }
+}
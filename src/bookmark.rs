@@ -0,0 +1,105 @@
+use crate::feed::Item;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/*
+ * ブックマークした記事
+ * 元のフィードから消えても読めるよう、記事そのものを新しい順にJSONファイルへ保存する
+ */
+#[derive(Default)]
+pub struct Bookmarks {
+    path: Option<PathBuf>, //保存先 Noneならメモリ上だけで覚えておく
+    items: Vec<Item>,
+}
+
+impl Bookmarks {
+    /*pathから読み出す ファイルがなければブックマークのない状態から始める*/
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.into();
+        let items = match fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: Some(path),
+            items,
+        })
+    }
+
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    /*ブックマークしていなければ先頭に加え、していれば外す ブックマークした状態になったらtrueを返す*/
+    pub fn toggle(&mut self, item: &Item) -> bool {
+        let id = item.id();
+        let len = self.items.len();
+        self.items.retain(|bookmark| bookmark.id() != id);
+        if self.items.len() < len {
+            return false;
+        }
+        self.items.insert(0, item.clone());
+        true
+    }
+
+    /*一時ファイルに書いてから置き換える*/
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string(&self.items)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::PubDate;
+    #[test]
+    fn bookmarks_test() {
+        let path =
+            std::env::temp_dir().join(format!("rss-reader-bookmarks-{}.json", std::process::id()));
+        let mut bookmarks = Bookmarks::open(&path).unwrap();
+        let item = Item {
+            title: "あとで読む".to_string(),
+            link: Some("https://example.com/1".to_string()),
+            guid: None,
+            pub_date: Some(PubDate::parse("2021-08-12T11:37:05+09:00")),
+            description: Some("概要".to_string()),
+            content: None,
+            author: None,
+            categories: Vec::new(),
+            image: None,
+        };
+        assert!(bookmarks.toggle(&item));
+        assert_eq!(bookmarks.items()[0].id(), item.id());
+        bookmarks.save().unwrap();
+
+        let mut bookmarks = Bookmarks::open(&path).unwrap();
+        assert_eq!(bookmarks.items().len(), 1);
+        assert_eq!(bookmarks.items()[0].description.as_deref(), Some("概要"));
+        assert_eq!(bookmarks.items()[0].pub_date, item.pub_date);
+        //同じ記事なら外し、別の記事は先頭に加える
+        assert!(!bookmarks.toggle(&item));
+        assert!(bookmarks.items().is_empty());
+        let other = Item {
+            title: "別の記事".to_string(),
+            ..item.clone()
+        };
+        bookmarks.toggle(&other);
+        bookmarks.toggle(&item);
+        assert_eq!(bookmarks.items()[0].title, "あとで読む");
+        assert_eq!(bookmarks.items()[1].title, "別の記事");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,145 @@
+use crate::bookmark::Bookmarks;
+use crate::feed::Feed;
+use crate::fetch::Validators;
+use crate::read::ReadLog;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/*
+ * 取得したフィードをurlごとにJSONファイルとして保存しておくディレクトリ
+ * 既読の記録とブックマークは消されても取得し直せるものではないので、別のdata_dirに保存する
+ */
+pub struct Cache {
+    dir: PathBuf,
+    data_dir: PathBuf,
+}
+
+/*保存するフィードと、取得元のurlと取得した時刻*/
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub url: String,
+    pub fetched_at: DateTime<Utc>,
+    pub validators: Validators, //次回の条件付きGETに使う値
+    pub feed: Feed,
+}
+
+impl Cache {
+    /*既読の記録とブックマークも同じディレクトリに保存する*/
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        let dir = dir.into();
+        Self {
+            data_dir: dir.clone(),
+            dir,
+        }
+    }
+
+    pub fn with_data_dir<P: Into<PathBuf>, Q: Into<PathBuf>>(dir: P, data_dir: Q) -> Self {
+        Self {
+            dir: dir.into(),
+            data_dir: data_dir.into(),
+        }
+    }
+
+    /*urlのフィードを読み出す 保存されていなければNone*/
+    pub fn load(&self, url: &str) -> Result<Option<Entry>, Box<dyn Error>> {
+        let s = match fs::read_to_string(self.path(url)) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let entry: Entry = serde_json::from_str(&s)?;
+        //ファイル名が同じになった別のurlのものは使わない
+        Ok(Some(entry).filter(|entry| entry.url == url))
+    }
+
+    /*一時ファイルに書いてから置き換え、書き込みの途中で止まっても壊れたファイルを残さない*/
+    pub fn save(&self, entry: &Entry) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(&entry.url);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string(entry)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /*data_dirに保存しておいた既読の記録を読み出す*/
+    pub fn read_log(&self) -> io::Result<ReadLog> {
+        ReadLog::open(self.data_dir.join("read.txt"))
+    }
+
+    /*data_dirに保存しておいたブックマークを読み出す*/
+    pub fn bookmarks(&self) -> Result<Bookmarks, Box<dyn Error>> {
+        Bookmarks::open(self.data_dir.join("bookmarks.json"))
+    }
+
+    /*urlのスキーム以降の英数字と.と-以外を_にしたファイル名*/
+    fn path(&self, url: &str) -> PathBuf {
+        let name = url
+            .split("://")
+            .last()
+            .unwrap_or(url)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        self.dir.join(format!("{}.json", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::Schedule;
+    #[test]
+    fn save_load_test() {
+        let dir = std::env::temp_dir().join(format!("rss-reader-cache-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let url = "https://news.yahoo.co.jp/rss/topics/it.xml";
+        assert!(cache.load(url).unwrap().is_none());
+        assert_eq!(
+            cache.path(url),
+            dir.join("news.yahoo.co.jp_rss_topics_it.xml.json")
+        );
+
+        let entry = Entry {
+            url: url.to_string(),
+            fetched_at: Utc::now(),
+            validators: Validators {
+                etag: Some("\"v1\"".to_string()),
+                last_modified: None,
+            },
+            feed: Feed {
+                title: "IT".to_string(),
+                link: None,
+                description: None,
+                items: Vec::new(),
+                warnings: Vec::new(),
+                schedule: Schedule {
+                    ttl: Some(15),
+                    ..Schedule::default()
+                },
+            },
+        };
+        cache.save(&entry).unwrap();
+        let loaded = cache.load(url).unwrap().unwrap();
+        assert_eq!(loaded.fetched_at, entry.fetched_at);
+        assert_eq!(loaded.validators, entry.validators);
+        assert_eq!(loaded.feed.title, "IT");
+        assert_eq!(loaded.feed.schedule.ttl, Some(15));
+        //同じファイル名になる別のurlは読み出さない
+        assert!(cache
+            .load("https://news.yahoo.co.jp/rss/topics_it.xml")
+            .unwrap()
+            .is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+use encoding_rs::{Encoding, UTF_8};
+use std::ops::Range;
+
+/*
+ * 取得したフィードの文字コードを判別してUTF-8の文字列にする
+ * Content-Typeのcharset、XML宣言のencoding、BOMの順に調べ、どれもなければUTF-8とみなす
+ */
+pub fn decode(bytes: &[u8], content_type: Option<&str>) -> String {
+    let encoding = content_type
+        .and_then(header_charset)
+        .or_else(|| xml_encoding(bytes))
+        .or_else(|| Encoding::for_bom(bytes).map(|(encoding, _)| encoding))
+        .unwrap_or(UTF_8);
+    let (s, _) = encoding.decode_with_bom_removal(bytes);
+    //XMLパーサーは宣言のencodingで読もうとするので、変換した文字コードに合わせて書き換える
+    match utf8_declaration(&s) {
+        Some(s) => s,
+        None => s.into_owned(),
+    }
+}
+
+/*
+ * XML宣言のencodingを"UTF-8"にした文書 宣言がなければNone
+ * 宣言は文書の先頭にしか置けないので、宣言の前の空白も取り除く
+ */
+fn utf8_declaration(s: &str) -> Option<String> {
+    let s = s.trim_start();
+    let end = declaration(s)?.len();
+    let (decl, rest) = s.split_at(end);
+    Some(match encoding_label(decl) {
+        Some(label) => format!(
+            "{}UTF-8{}{}",
+            &decl[..label.start],
+            &decl[label.end..],
+            rest
+        ),
+        None => s.to_string(),
+    })
+}
+
+/*"text/xml; charset=Shift_JIS"のようなContent-Typeからcharsetを取り出す*/
+fn header_charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(
+            value
+                .trim()
+                .trim_matches(|c| c == '"' || c == '\'')
+                .as_bytes(),
+        )
+    })
+}
+
+/*先頭の<?xml ... encoding="EUC-JP"?>からencodingを取り出す*/
+fn xml_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    //宣言はASCIIなので、Shift_JISやEUC-JPのままでも先頭部分は読める
+    let head = &bytes[..bytes.len().min(1024)];
+    let head = String::from_utf8_lossy(head);
+    let decl = declaration(head.trim_start_matches('\u{feff}').trim_start())?;
+    Encoding::for_label(decl[encoding_label(decl)?].trim().as_bytes())
+}
+
+/*sの先頭のXML宣言(<?xml ... ?>)*/
+fn declaration(s: &str) -> Option<&str> {
+    let after = s.strip_prefix("<?xml")?;
+    //<?xml-stylesheet ...?>のような処理命令は宣言ではない
+    if !after.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(&s[..after.find("?>")? + 7])
+}
+
+/*XML宣言の中のencodingの値の位置*/
+fn encoding_label(decl: &str) -> Option<Range<usize>> {
+    let start = decl.find("encoding")? + "encoding".len();
+    let after = decl[start..].trim_start().strip_prefix('=')?.trim_start();
+    let quote = after.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let start = decl.len() - after.len() + 1;
+    let len = decl[start..].find(quote)?;
+    Some(start..start + len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::Feed;
+    use encoding_rs::{EUC_JP, SHIFT_JIS, UTF_16LE};
+    #[test]
+    fn decode_test() {
+        //XML宣言のencodingは変換後のUTF-8に書き換える
+        let xml = r#"<?xml version="1.0" encoding="Shift_JIS"?><rss><channel><title>天気予報</title></channel></rss>"#;
+        let utf8 = xml.replace("Shift_JIS", "UTF-8");
+        let (sjis, _, _) = SHIFT_JIS.encode(xml);
+        assert_eq!(decode(&sjis, None), utf8);
+        assert_eq!(decode(&sjis, Some("application/rss+xml")), utf8);
+        //宣言の前の空白は取り除く
+        let indented = format!("\n  {}", xml);
+        let (sjis, _, _) = SHIFT_JIS.encode(&indented);
+        assert_eq!(decode(&sjis, None), utf8);
+
+        //Content-TypeはXML宣言より優先する
+        let xml = r#"<?xml version='1.0' encoding='UTF-8'?><rss><channel><title>天気予報</title></channel></rss>"#;
+        let (euc, _, _) = EUC_JP.encode(xml);
+        assert_eq!(decode(&euc, Some(r#"text/xml; charset="EUC-JP""#)), xml);
+
+        //宣言もContent-TypeもなければBOMを見る
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend(
+            "{\"title\": \"天気\"}"
+                .encode_utf16()
+                .flat_map(|unit| unit.to_le_bytes().to_vec()),
+        );
+        assert_eq!(Encoding::for_bom(&utf16).unwrap().0, UTF_16LE);
+        assert_eq!(decode(&utf16, None), "{\"title\": \"天気\"}");
+        assert_eq!(decode("\u{feff}<rss/>".as_bytes(), None), "<rss/>");
+        assert_eq!(
+            decode("<rss/>".as_bytes(), Some("text/xml; charset=unknown")),
+            "<rss/>"
+        );
+    }
+
+    #[test]
+    fn label_test() {
+        assert_eq!(
+            header_charset("text/xml; Charset=shift_jis"),
+            Some(SHIFT_JIS)
+        );
+        assert_eq!(header_charset("text/xml"), None);
+        assert_eq!(
+            xml_encoding(b"\n<?xml version=\"1.0\" encoding = \"euc-jp\" ?>\n<rss/>"),
+            Some(EUC_JP)
+        );
+        assert_eq!(
+            xml_encoding(b"<?xml version=\"1.0\"?><rss encoding=\"EUC-JP\"/>"),
+            None
+        );
+        assert_eq!(xml_encoding(b"<rss/>"), None);
+        assert_eq!(
+            xml_encoding(b"<?xml-stylesheet href=\"a.xsl\" encoding=\"EUC-JP\"?><rss/>"),
+            None
+        );
+    }
+
+    #[test]
+    fn decode_feed_test() {
+        //Shift_JISやEUC-JPのフィードも変換してから読み込める
+        for (label, encoding) in &[("Shift_JIS", SHIFT_JIS), ("EUC-JP", EUC_JP)] {
+            let xml = format!(
+                r#"<?xml version="1.0" encoding="{}"?>
+                <rss version="2.0"><channel><title>地域のニュース</title>
+                    <item><title>台風が接近</title><description>雨に注意</description></item>
+                </channel></rss>"#,
+                label
+            );
+            let (bytes, _, _) = encoding.encode(&xml);
+            let feed = Feed::new(&decode(&bytes, Some("application/rss+xml"))).unwrap();
+            assert_eq!(feed.title, "地域のニュース", "{}", label);
+            assert_eq!(feed.items[0].title, "台風が接近", "{}", label);
+            assert_eq!(feed.items[0].description.as_deref(), Some("雨に注意"));
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use crate::fetch::Policy;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/*設定ファイル(TOML)の内容 省略した項目は既定値になる*/
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub long_press_ms: u64, //長押しとみなすまでの時間(ミリ秒)
+    pub keymap: HashMap<String, HashMap<String, String>>, //画面ごとのボタン操作と動作の対応
+    pub min_interval_secs: u64, //フィードを取得し直すまでの最小間隔(秒)
+    pub max_items: usize,   //フィードから読み込む記事の上限
+    pub connect_timeout_secs: u64, //接続できるまで待つ時間(秒)
+    pub read_timeout_secs: u64, //応答の続きが届くまで待つ時間(秒)
+    pub retries: u32,       //取得に一時的に失敗したときにやり直す回数
+    pub max_body_bytes: u64, //受信する内容の上限(バイト)
+    pub cache_dir: PathBuf, //取得したフィードを保存するディレクトリ
+    pub data_dir: PathBuf,  //既読の記録やブックマークを保存するディレクトリ
+    pub prefetch_concurrency: usize, //起動時に同時に取得するフィードの数
+    pub feeds: HashMap<String, FeedConfig>, //カテゴリ名ごとの設定
+}
+
+/*カテゴリごとの設定 省略した項目は全体の設定に従う*/
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct FeedConfig {
+    pub url: Option<String>, //取得先 既定のカテゴリなら置き換え、それ以外なら新しいカテゴリとして加える
+    pub min_interval_secs: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            long_press_ms: 800,
+            keymap: HashMap::new(),
+            min_interval_secs: 300,
+            max_items: 100,
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            retries: 2,
+            max_body_bytes: 5 * 1024 * 1024,
+            cache_dir: default_cache_dir(),
+            data_dir: default_data_dir(),
+            prefetch_concurrency: 3,
+            feeds: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn new(s: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(s)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(&fs::read_to_string(path)?)?)
+    }
+
+    /*
+     * 既定のカテゴリ名とurlの組に、[feeds."<カテゴリ名>"]のurlを反映したもの
+     * 既定にないカテゴリは既定のカテゴリの後ろに名前の順に並べる
+     */
+    pub fn categories(&self, defaults: &[(&str, &str)]) -> Vec<(String, String)> {
+        let url = |name: &str| self.feeds.get(name).and_then(|feed| feed.url.clone());
+        let mut categories = defaults
+            .iter()
+            .map(|(name, default)| {
+                let url = url(name).unwrap_or_else(|| default.to_string());
+                (name.to_string(), url)
+            })
+            .collect::<Vec<_>>();
+        let mut added = self
+            .feeds
+            .keys()
+            .filter(|name| defaults.iter().all(|(default, _)| default != name))
+            .filter_map(|name| Some((name.clone(), url(name)?)))
+            .collect::<Vec<_>>();
+        added.sort();
+        categories.append(&mut added);
+        categories
+    }
+
+    /*カテゴリのフィードを取得し直すまでの最小間隔*/
+    pub fn min_interval(&self, category: &str) -> Duration {
+        let secs = self
+            .feeds
+            .get(category)
+            .and_then(|feed| feed.min_interval_secs)
+            .unwrap_or(self.min_interval_secs);
+        Duration::from_secs(secs)
+    }
+
+    /*フィードや画像を取得するときの方針*/
+    pub fn fetch_policy(&self) -> Policy {
+        Policy {
+            connect_timeout: Duration::from_secs(self.connect_timeout_secs),
+            read_timeout: Duration::from_secs(self.read_timeout_secs),
+            retries: self.retries,
+            max_bytes: self.max_body_bytes,
+            ..Policy::default()
+        }
+    }
+}
+
+/*$XDG_CACHE_HOMEか~/.cacheの下のディレクトリ*/
+fn default_cache_dir() -> PathBuf {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(|| PathBuf::from(".cache"))
+        .join("nanohat-oled-rss-reader")
+}
+
+/*$XDG_DATA_HOMEか~/.local/shareの下のディレクトリ キャッシュと違い消されると困るものを置く*/
+fn default_data_dir() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+        })
+        .unwrap_or_else(|| PathBuf::from(".local/share"))
+        .join("nanohat-oled-rss-reader")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn make_config_test() {
+        let config = Config::new(
+            r#"
+            long_press_ms = 1000
+            retries = 0
+
+            cache_dir = "/var/cache/rss"
+            data_dir = "/var/lib/rss"
+
+            [keymap.title]
+            f1 = "up"
+            f3 = "down"
+            long_f2 = "refresh"
+
+            [feeds."スポーツ"]
+            min_interval_secs = 60
+
+            [feeds."IT"]
+            url = "https://example.com/it.xml"
+
+            [feeds."障害情報"]
+            url = "https://status.example.com/feed.json"
+
+            [feeds."URLなし"]
+            min_interval_secs = 60
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.long_press_ms, 1000);
+        let title = config.keymap.get("title").unwrap();
+        assert_eq!(title.get("f1").unwrap(), "up");
+        assert_eq!(title.get("long_f2").unwrap(), "refresh");
+        assert_eq!(config.min_interval("スポーツ"), Duration::from_secs(60));
+        assert_eq!(config.min_interval("主要"), Duration::from_secs(300));
+        assert_eq!(config.fetch_policy().retries, 0);
+        assert_eq!(config.cache_dir, PathBuf::from("/var/cache/rss"));
+        assert_eq!(config.data_dir, PathBuf::from("/var/lib/rss"));
+        assert_eq!(config.fetch_policy().read_timeout, Duration::from_secs(30));
+        assert_eq!(
+            config.categories(&[("主要", "https://example.com/top.xml"), ("IT", "")]),
+            [
+                (
+                    "主要".to_string(),
+                    "https://example.com/top.xml".to_string()
+                ),
+                ("IT".to_string(), "https://example.com/it.xml".to_string()),
+                (
+                    "障害情報".to_string(),
+                    "https://status.example.com/feed.json".to_string()
+                ),
+            ]
+        );
+
+        let config = Config::new("").unwrap();
+        assert_eq!(config.long_press_ms, 800);
+        assert!(config.keymap.is_empty());
+        assert_eq!(config.max_items, 100);
+        assert!(config.cache_dir.ends_with("nanohat-oled-rss-reader"));
+        assert!(config.data_dir.ends_with("nanohat-oled-rss-reader"));
+        assert_ne!(config.data_dir, config.cache_dir);
+    }
+}
@@ -0,0 +1,179 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/*
+ * フィードの日付
+ * 解釈できなかった場合も元の文字列はそのまま表示に使う
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PubDate {
+    pub raw: String,
+    pub time: Option<DateTime<FixedOffset>>,
+}
+
+impl PubDate {
+    pub fn parse(raw: &str) -> Self {
+        Self {
+            raw: raw.to_string(),
+            time: parse(raw),
+        }
+    }
+
+    /*nowを基準に"5分前"や"2時間前"のような表示用の文字列にする*/
+    pub fn relative(&self, now: DateTime<Utc>) -> String {
+        match self.time {
+            Some(time) => relative(time, now),
+            None => self.raw.trim().to_string(),
+        }
+    }
+}
+
+/*nowを基準にtimeを"5分前"や"2時間前"、30日以上前なら日付で表す*/
+pub fn relative<Tz: TimeZone>(time: DateTime<Tz>, now: DateTime<Utc>) -> String
+where
+    Tz::Offset: fmt::Display,
+{
+    let minutes = now.signed_duration_since(time.clone()).num_minutes();
+    if minutes < 1 {
+        //時計のずれで未来の日付になることもある
+        "たった今".to_string()
+    } else if minutes < 60 {
+        format!("{}分前", minutes)
+    } else if minutes < 60 * 24 {
+        format!("{}時間前", minutes / 60)
+    } else if minutes < 60 * 24 * 30 {
+        format!("{}日前", minutes / (60 * 24))
+    } else {
+        time.format("%Y/%m/%d").to_string()
+    }
+}
+
+/*ISO 8601(RFC 3339)とRFC 822(RFC 2822)、およびそれらによくある崩れた形を解釈する*/
+pub fn parse(s: &str) -> Option<DateTime<FixedOffset>> {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+    parse_iso8601(s).or_else(|| parse_rfc822(s))
+}
+
+fn parse_iso8601(s: &str) -> Option<DateTime<FixedOffset>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Some(time);
+    }
+    //日付と時刻の区切りが空白のもの、タイムゾーンにコロンがないもの
+    for format in &[
+        "%Y-%m-%d %H:%M:%S%.f%:z",
+        "%Y-%m-%dT%H:%M:%S%.f%z",
+        "%Y-%m-%d %H:%M:%S%.f%z",
+    ] {
+        if let Ok(time) = DateTime::parse_from_str(s, format) {
+            return Some(time);
+        }
+    }
+    //タイムゾーンのないものはUTCとみなす
+    let utc = FixedOffset::east_opt(0)?;
+    for format in &[
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y/%m/%d %H:%M:%S",
+    ] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(s, format) {
+            return utc.from_local_datetime(&time).single();
+        }
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+    utc.from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .single()
+}
+
+fn parse_rfc822(s: &str) -> Option<DateTime<FixedOffset>> {
+    //"(JST)"のような末尾の注釈を取り除く
+    let s = match s.find('(') {
+        Some(i) => &s[..i],
+        None => s,
+    };
+    //曜日は間違っていることがあるので読まない
+    let s = match s.find(',') {
+        Some(i) => &s[i + 1..],
+        None => s,
+    };
+    let mut words = s.split_whitespace().collect::<Vec<_>>();
+    let zone = words.last().and_then(|last| zone_offset(last));
+    if let Some(zone) = zone {
+        words.pop();
+        words.push(zone);
+    }
+    DateTime::parse_from_rfc2822(&words.join(" ")).ok()
+}
+
+/*RFC 822にないが実際のフィードで見かけるタイムゾーン名*/
+fn zone_offset(name: &str) -> Option<&'static str> {
+    let offset = match name.to_ascii_uppercase().as_str() {
+        "Z" | "UTC" | "UT" | "GMT" => "+0000",
+        "JST" | "KST" => "+0900",
+        "CST" => "-0600", //中国標準時と紛らわしいがRFC 822の定義に従う
+        "EST" => "-0500",
+        "EDT" => "-0400",
+        "CDT" => "-0500",
+        "MST" => "-0700",
+        "MDT" => "-0600",
+        "PST" => "-0800",
+        "PDT" => "-0700",
+        "BST" | "CET" => "+0100",
+        "CEST" => "+0200",
+        "IST" => "+0530",
+        "AEST" => "+1000",
+        "AEDT" => "+1100",
+        _ => return None,
+    };
+    Some(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn parse_test() {
+        let time = |s: &str| DateTime::parse_from_rfc3339(s).unwrap();
+        let expected = time("2021-08-12T11:37:05+09:00");
+        for s in &[
+            "2021-08-12T02:37:05.000Z",
+            "2021-08-12T11:37:05+09:00",
+            "2021-08-12 11:37:05+09:00",
+            "2021-08-12T11:37:05+0900",
+            "Thu, 12 Aug 2021 11:37:05 +0900",
+            "Thu, 12 Aug 2021 11:37:05 JST",
+            "Thu, 12 Aug 2021 02:37:05 GMT",
+            "Mon, 12 Aug 2021 02:37:05 gmt",
+            " 12 Aug 2021 11:37:05 +0900 (JST) ",
+            "Wed, 11 Aug 2021 21:37:05 EST",
+            "2021-08-12T02:37:05",
+        ] {
+            assert_eq!(parse(s), Some(expected), "{}", s);
+        }
+        assert_eq!(
+            parse("Thu, 12 Aug 2021 11:37 +0900"),
+            Some(time("2021-08-12T11:37:00+09:00"))
+        );
+        assert_eq!(parse("2021-08-12"), Some(time("2021-08-12T00:00:00+00:00")));
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("昨日"), None);
+        assert_eq!(parse("Thu, 12 Aug 2021 11:37:05 XYZ"), None);
+    }
+
+    #[test]
+    fn relative_test() {
+        let now = "2021-08-12T03:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let relative = |s: &str| PubDate::parse(s).relative(now);
+        assert_eq!(relative("2021-08-12T11:59:30+09:00"), "たった今");
+        assert_eq!(relative("2021-08-12T03:05:00Z"), "たった今");
+        assert_eq!(relative("2021-08-12T02:55:00Z"), "5分前");
+        assert_eq!(relative("Thu, 12 Aug 2021 10:00:00 +0900"), "2時間前");
+        assert_eq!(relative("2021-08-09T03:00:00Z"), "3日前");
+        assert_eq!(relative("2021-06-01T10:00:00+09:00"), "2021/06/01");
+        assert_eq!(relative(" 八月十二日 "), "八月十二日");
+    }
+}
@@ -0,0 +1,92 @@
+use crate::nanohat::oled::NanoHatOLED;
+use i2cdev::core::I2CDevice;
+use image::imageops::{dither, overlay, BiLevel};
+use image::GrayImage;
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+/*画面を描画する先を抽象化するトレイト*/
+pub trait Display {
+    fn draw_image(&mut self, img: &GrayImage, x: u8, page_y: u8) -> Result<(), Box<dyn Error>>;
+}
+
+impl<T> Display for NanoHatOLED<T>
+where
+    T: I2CDevice + Sized,
+    T::Error: 'static,
+{
+    fn draw_image(&mut self, img: &GrayImage, x: u8, page_y: u8) -> Result<(), Box<dyn Error>> {
+        NanoHatOLED::draw_image(self, img, x, page_y)
+    }
+}
+
+/*OLEDの代わりにメモリ上の128x64の画像へ描画する構造体*/
+pub struct SimulatedDisplay {
+    frame: GrayImage,         //現在表示されている画面
+    out_dir: Option<PathBuf>, //描画のたびに画面をPNGで保存する先
+    frame_count: usize,       //保存した画面の枚数
+}
+
+impl SimulatedDisplay {
+    pub fn new(out_dir: Option<PathBuf>) -> Self {
+        Self {
+            frame: GrayImage::new(128, 64),
+            out_dir,
+            frame_count: 0,
+        }
+    }
+
+    pub fn frame(&self) -> &GrayImage {
+        &self.frame
+    }
+}
+
+impl Display for SimulatedDisplay {
+    fn draw_image(&mut self, img: &GrayImage, x: u8, page_y: u8) -> Result<(), Box<dyn Error>> {
+        /*NanoHatOLEDと同じ条件で不正な画像を弾く*/
+        let (h, rem) = (img.height() / 8, img.height() % 8);
+        if x as u32 + img.width() > 128 || page_y as u32 + h > 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The Image's dimensions are too large",
+            )
+            .into());
+        } else if rem != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The Image's height is invalid",
+            )
+            .into());
+        }
+
+        let mut img = img.clone();
+        dither(&mut img, &BiLevel); //実機と同じく2値化してから描画
+        overlay(&mut self.frame, &img, x as u32, page_y as u32 * 8);
+
+        if let Some(dir) = &self.out_dir {
+            self.frame
+                .save(dir.join(format!("frame{:05}.png", self.frame_count)))?;
+        }
+        self.frame_count += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+    #[test]
+    fn draw_image_test() {
+        let mut display = SimulatedDisplay::new(None);
+        let mut img = GrayImage::new(8, 8);
+        img.put_pixel(0, 0, Luma([255]));
+        assert!(display.draw_image(&img, 120, 7).is_ok());
+        assert_eq!(display.frame().get_pixel(120, 56)[0], 255);
+        assert_eq!(display.frame().get_pixel(121, 56)[0], 0);
+        assert!(display.draw_image(&img, 121, 0).is_err());
+        assert!(display.draw_image(&img, 0, 8).is_err());
+        assert!(display.draw_image(&GrayImage::new(8, 9), 0, 0).is_err());
+    }
+}
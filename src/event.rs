@@ -0,0 +1,16 @@
+use crate::fetch::{self, Fetched};
+use crate::keymap::Gesture;
+use crate::record::Outcome;
+use crate::remote::Command;
+use chrono::{DateTime, Utc};
+use tokio::sync::oneshot;
+
+/*メインループが処理する入力イベント*/
+pub enum Event {
+    Gesture(Gesture), //ボタンまたは記録の再生による入力
+    Remote(Command, oneshot::Sender<Result<String, String>>), //リモートからのコマンドと応答の送り先
+    Tick,             //フィードを取得し直す時期かを確かめる合図
+    Prefetched(usize, DateTime<Utc>, Result<Fetched, fetch::Error>), //起動時の先読みで取得したカテゴリと取得を始めた時刻、結果
+    Refreshed(usize, DateTime<Utc>, Result<Fetched, fetch::Error>), //定期的な取得で取得したカテゴリと取得を始めた時刻、結果
+    Replayed(usize, Outcome), //記録の再生で、次の入力の処理の中で取得したことにするカテゴリと結果
+}
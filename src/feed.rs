@@ -0,0 +1,334 @@
+use crate::atom::Atom;
+use crate::date::PubDate;
+use crate::json_feed::JsonFeed;
+use crate::rdf::RDF;
+use crate::rss::RSS;
+use chrono::Weekday;
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+
+/*フィードの形式によらず画面表示に使う情報*/
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Feed {
+    pub title: String,
+    pub link: Option<String>,
+    pub description: Option<String>,
+    pub items: Vec<Item>,
+    pub warnings: Vec<Warning>, //読み込めたが仕様に沿っていなかった箇所
+    pub schedule: Schedule,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Item {
+    pub title: String,
+    pub link: Option<String>,
+    pub guid: Option<String>, //記事を一意に表す値(guid、Atomのid、rdf:aboutなど)
+    pub pub_date: Option<PubDate>, //日付(解釈できなければ元の文字列のみ)
+    pub description: Option<String>, //概要
+    pub content: Option<String>, //本文
+    pub author: Option<String>, //著者(dc:creatorなど)
+    pub categories: Vec<String>, //カテゴリやタグ
+    pub image: Option<String>, //画像のurl
+}
+
+impl Item {
+    /*
+     * 既読などの記録に使う記事を見分ける値
+     * guidがなければlinkとタイトルのハッシュ(再起動しても変わらないようFNV-1aで求める)
+     */
+    pub fn id(&self) -> String {
+        if let Some(guid) = &self.guid {
+            return guid.clone();
+        }
+        let link = self.link.as_deref().unwrap_or("");
+        let hash = format!("{}\n{}", link, self.title)
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+                (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+            });
+        format!("{:016x}", hash)
+    }
+}
+
+/*フィードの読み込み時に見つかった問題 itemがNoneならチャンネル全体に関するもの*/
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Warning {
+    pub item: Option<usize>,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn channel<S: Into<String>>(message: S) -> Self {
+        Self {
+            item: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn item<S: Into<String>>(i: usize, message: S) -> Self {
+        Self {
+            item: Some(i),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.item {
+            Some(i) => write!(f, "item {}: {}", i, self.message),
+            None => write!(f, "channel: {}", self.message),
+        }
+    }
+}
+
+/*フィードが指定する取得の間隔(RSS 2.0のttl、skipHours、skipDays)*/
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub ttl: Option<u32>,        //取得し直すまでの分数
+    pub skip_hours: Vec<u32>,    //取得しない時(GMTの0から23)
+    pub skip_days: Vec<Weekday>, //取得しない曜日
+}
+
+/*対応しているフィードの形式*/
+#[derive(Debug, PartialEq)]
+pub enum Format {
+    Rss1,
+    Rss2,
+    Atom,
+    Json,
+}
+
+/*
+ * フィードを読み込めなかった理由
+ * Parseのpositionは問題のあった1始まりの行と列で、わからなければNone
+ */
+#[derive(Debug)]
+pub enum Error {
+    Parse {
+        position: Option<(u64, u64)>,
+        reason: String,
+    },
+    UnknownFormat(String), //ルート要素の名前
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse {
+                position: Some((line, column)),
+                reason,
+            } => write!(f, "line {}, column {}: {}", line, column, reason),
+            Error::Parse {
+                position: None,
+                reason,
+            } => write!(f, "{}", reason),
+            Error::UnknownFormat(root) => write!(f, "unknown feed format: <{}>", root),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<xml::reader::Error> for Error {
+    fn from(e: xml::reader::Error) -> Self {
+        let position = e.position();
+        Error::Parse {
+            position: Some((position.row + 1, position.column + 1)),
+            reason: e.msg().to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        //serde_jsonのメッセージは末尾に位置が付いているので取り除く
+        let (line, column) = (e.line() as u64, e.column() as u64);
+        let message = e.to_string();
+        let suffix = format!(" at line {} column {}", line, column);
+        Error::Parse {
+            position: if line > 0 { Some((line, column)) } else { None },
+            reason: message.trim_end_matches(suffix.as_str()).to_string(),
+        }
+    }
+}
+
+impl Feed {
+    /*内容から形式を判別して読み込む*/
+    pub fn new(s: &str) -> Result<Self, Error> {
+        Self::with_format(s, detect(s)?, None)
+    }
+
+    /*指定した形式で読み込み、itemは先頭からmax_items件までにする*/
+    pub fn with_format(s: &str, format: Format, max_items: Option<usize>) -> Result<Self, Error> {
+        let mut feed: Feed = match format {
+            Format::Rss1 => RDF::with_limit(s, max_items)?.into(),
+            Format::Rss2 => RSS::with_limit(s, max_items)?.into(),
+            Format::Atom => Atom::with_limit(s, max_items)?.into(),
+            Format::Json => JsonFeed::new(s)?.into(),
+        };
+        //JSON Feedは全体を読んでから切り詰める
+        if let Some(max_items) = max_items {
+            feed.items.truncate(max_items);
+        }
+        Ok(feed)
+    }
+}
+
+/*
+ * Content-Typeかurlの拡張子から形式を推定する
+ * XMLの各形式はここでは区別できないのでNoneを返し、内容からの判別に任せる
+ */
+pub fn sniff(content_type: Option<&str>, url: &str) -> Option<Format> {
+    let mime = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|mime| mime.trim().to_ascii_lowercase());
+    if let Some("application/feed+json") | Some("application/json") = mime.as_deref() {
+        return Some(Format::Json);
+    }
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    if path.to_ascii_lowercase().ends_with(".json") {
+        return Some(Format::Json);
+    }
+    None
+}
+
+/*JSONなら先頭の文字で、XMLなら最初に現れる要素の名前で形式を判別する*/
+pub fn detect(s: &str) -> Result<Format, Error> {
+    if s.trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with('{')
+    {
+        return Ok(Format::Json);
+    }
+    for event in EventReader::new(s.as_bytes()) {
+        let event = event?;
+        if let XmlEvent::StartElement { name, .. } = event {
+            return match name.local_name.as_str() {
+                "RDF" => Ok(Format::Rss1),
+                "rss" => Ok(Format::Rss2),
+                "feed" => Ok(Format::Atom),
+                _ => Err(Error::UnknownFormat(name.local_name)),
+            };
+        }
+    }
+    Err(Error::UnknownFormat(String::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn detect_test() {
+        assert_eq!(
+            detect(r#"<?xml version="1.0"?><rss version="2.0"></rss>"#).unwrap(),
+            Format::Rss2
+        );
+        assert_eq!(
+            detect(r#"<feed xmlns="http://www.w3.org/2005/Atom"></feed>"#).unwrap(),
+            Format::Atom
+        );
+        assert_eq!(
+            detect(r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"/>"#)
+                .unwrap(),
+            Format::Rss1
+        );
+        assert_eq!(
+            detect("\u{feff} {\"version\": \"https://jsonfeed.org/version/1.1\"}").unwrap(),
+            Format::Json
+        );
+        assert!(matches!(
+            detect("<html></html>"),
+            Err(Error::UnknownFormat(root)) if root == "html"
+        ));
+        assert!(matches!(
+            detect("<rss"),
+            Err(Error::Parse {
+                position: Some(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let e = Feed::new("<rss version=\"2.0\">\n<channel>\n<title>a</titl>\n</channel></rss>")
+            .err()
+            .unwrap();
+        assert!(matches!(
+            e,
+            Error::Parse {
+                position: Some((3, _)),
+                ..
+            }
+        ));
+        assert!(e.to_string().starts_with("line 3, column "));
+
+        let e = Feed::new("{\n  \"title\": }").err().unwrap();
+        assert!(matches!(
+            &e,
+            Error::Parse {
+                position: Some((2, 12)),
+                reason,
+            } if !reason.contains("at line")
+        ));
+    }
+
+    #[test]
+    fn sniff_test() {
+        assert_eq!(
+            sniff(
+                Some("application/feed+json; charset=utf-8"),
+                "https://example.com/feed"
+            ),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            sniff(Some("Application/JSON"), "https://example.com/feed"),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            sniff(None, "https://example.com/feed.JSON?page=1"),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            sniff(Some("application/rss+xml"), "https://example.com/feed.xml"),
+            None
+        );
+        assert_eq!(sniff(None, "https://example.com/json/feed"), None);
+    }
+
+    #[test]
+    fn make_feed_test() {
+        let feed = Feed::new(
+            r#"
+            <feed xmlns="http://www.w3.org/2005/Atom">
+                <title>Atomです</title>
+                <entry><title>エントリーです</title></entry>
+            </feed>
+        "#,
+        )
+        .unwrap();
+        assert_eq!(feed.title, "Atomです");
+        assert_eq!(feed.items.first().unwrap().title, "エントリーです");
+    }
+
+    #[test]
+    fn item_id_test() {
+        let feed = Feed::new(
+            r#"
+            <rss version="2.0"><channel><title>RSS</title>
+                <item><title>タイトル</title><guid>post-1</guid></item>
+                <item><title>タイトル</title><link>https://example.com/1</link></item>
+            </channel></rss>
+        "#,
+        )
+        .unwrap();
+        assert_eq!(feed.items[0].id(), "post-1");
+        //guidがなければlinkとタイトルのハッシュ 起動し直しても同じ値になる
+        assert_eq!(feed.items[1].id(), "635d50152744f41f");
+    }
+}
@@ -0,0 +1,446 @@
+use crate::charset;
+use crate::feed::{self, Feed};
+use reqwest::header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use tokio::time::{delay_for, timeout};
+
+/*フィードを取得して読み込む 複製したものは接続を共有する*/
+#[derive(Clone)]
+pub struct Fetcher {
+    client: Client,
+    policy: Policy,
+    max_items: Option<usize>, //読み込むitemの上限
+}
+
+/*取得の待ち時間ややり直しの方針*/
+#[derive(Clone, Debug)]
+pub struct Policy {
+    pub connect_timeout: Duration, //接続できるまで待つ時間
+    pub read_timeout: Duration,    //応答の続きが届くまで待つ時間
+    pub retries: u32,              //一時的な失敗をやり直す回数
+    pub backoff: Duration,         //最初にやり直すまでの時間 やり直すたびに倍にする
+    pub max_bytes: u64,            //受信する内容の上限(バイト)
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            retries: 2,
+            backoff: Duration::from_secs(1),
+            max_bytes: 5 * 1024 * 1024,
+        }
+    }
+}
+
+/*条件付きGETで前回から変わったかをサーバーに問い合わせるための値*/
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,          //ETag
+    pub last_modified: Option<String>, //Last-Modified
+}
+
+/*フィードの取得結果*/
+pub enum Fetched {
+    Modified(Box<Feed>, Validators), //新しい内容と次回の問い合わせに使う値
+    NotModified,                     //前回から変わっていない(304 Not Modified)
+}
+
+/*まとめて取得するときの1件分 indexは結果を受け取る側がフィードを見分けるためのもの*/
+pub struct Request {
+    pub index: usize,
+    pub url: String,
+    pub validators: Validators,
+}
+
+/*受信した応答の内容*/
+struct Body {
+    content_type: Option<String>,
+    bytes: Vec<u8>,
+    validators: Validators,
+}
+
+/*取得できなかったurlと理由*/
+#[derive(Debug)]
+pub struct Error {
+    pub url: String,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    Timeout,                 //接続や受信が時間内に終わらない
+    Dns(reqwest::Error),     //名前解決の失敗
+    Tls(reqwest::Error),     //TLSの接続の失敗
+    Connect(reqwest::Error), //それ以外の接続の失敗
+    Status(StatusCode),      //成功以外の応答
+    TooLarge(u64),           //内容が上限(バイト)を超えた
+    Request(reqwest::Error), //それ以外の通信の失敗
+    Parse(feed::Error),      //受信した内容をフィードとして読み込めない
+    Recorded(String),        //記録の再生で再現した通信の失敗(記録したときの原因)
+}
+
+impl ErrorKind {
+    /*やり直せば成功するかもしれない失敗か*/
+    fn is_transient(&self) -> bool {
+        match self {
+            ErrorKind::Timeout
+            | ErrorKind::Dns(_)
+            | ErrorKind::Connect(_)
+            | ErrorKind::Request(_) => true,
+            ErrorKind::Status(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            ErrorKind::Tls(_)
+            | ErrorKind::TooLarge(_)
+            | ErrorKind::Parse(_)
+            | ErrorKind::Recorded(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.url, self.kind)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Timeout => write!(f, "timed out"),
+            ErrorKind::Dns(e)
+            | ErrorKind::Tls(e)
+            | ErrorKind::Connect(e)
+            | ErrorKind::Request(e) => {
+                //reqwestのメッセージはurlを含むので原因だけを表示する
+                match error::Error::source(e) {
+                    Some(source) => write!(f, "{}", source),
+                    None => write!(f, "{}", e),
+                }
+            }
+            ErrorKind::Status(status) => write!(f, "HTTP status {}", status),
+            ErrorKind::TooLarge(max) => write!(f, "body exceeds {} bytes", max),
+            ErrorKind::Parse(e) => write!(f, "{}", e),
+            ErrorKind::Recorded(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/*reqwestのエラーを原因をたどって分類する*/
+impl From<reqwest::Error> for ErrorKind {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return ErrorKind::Timeout;
+        }
+        let (mut dns, mut tls) = (false, false);
+        let mut source = error::Error::source(&e);
+        while let Some(err) = source {
+            //TLSのエラーはio::Errorに包まれていることがある
+            let inner = err
+                .downcast_ref::<io::Error>()
+                .and_then(|e| e.get_ref())
+                .map(|inner| inner as &(dyn error::Error + 'static));
+            tls |= err.is::<native_tls::Error>()
+                || matches!(inner, Some(inner) if inner.is::<native_tls::Error>());
+            //hyperの名前解決のエラーは型が公開されていないのでメッセージで判別する
+            dns |= err.to_string().starts_with("dns error");
+            source = err.source();
+        }
+        if dns {
+            ErrorKind::Dns(e)
+        } else if tls {
+            ErrorKind::Tls(e)
+        } else if e.is_connect() {
+            ErrorKind::Connect(e)
+        } else {
+            ErrorKind::Request(e)
+        }
+    }
+}
+
+impl Fetcher {
+    pub fn new(max_items: Option<usize>) -> Self {
+        Self::with_policy(max_items, Policy::default())
+    }
+
+    pub fn with_policy(max_items: Option<usize>, policy: Policy) -> Self {
+        let client = Client::builder()
+            .connect_timeout(policy.connect_timeout)
+            .build()
+            .expect("failed to initialize the HTTP client");
+        Self {
+            client,
+            policy,
+            max_items,
+        }
+    }
+
+    /*
+     * フィードを取得して読み込む
+     * validatorsに前回の値があれば条件付きGETにし、変わっていなければ読み込まずにNotModifiedを返す
+     */
+    pub async fn fetch(&self, url: &str, validators: &Validators) -> Result<Fetched, Error> {
+        let body = match self.get(url, validators).await? {
+            Some(body) => body,
+            None => return Ok(Fetched::NotModified),
+        };
+        let content_type = body.content_type.as_deref();
+        let s = charset::decode(&body.bytes, content_type);
+        let feed = parse(&s, content_type, url, self.max_items).map_err(|e| Error {
+            url: url.to_string(),
+            kind: ErrorKind::Parse(e),
+        })?;
+        for warning in &feed.warnings {
+            eprintln!("{}: {}", url, warning);
+        }
+        Ok(Fetched::Modified(Box::new(feed), body.validators))
+    }
+
+    /*画像などフィード以外の内容を取得する*/
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>, Error> {
+        let body = self.get(url, &Validators::default()).await?;
+        Ok(body.map(|body| body.bytes).unwrap_or_default())
+    }
+
+    /*一時的な失敗は間隔を空けてやり直し、応答の内容を返す 変わっていなければNone*/
+    async fn get(&self, url: &str, validators: &Validators) -> Result<Option<Body>, Error> {
+        let mut attempt = 0;
+        loop {
+            match self.try_get(url, validators).await {
+                Err(kind) if kind.is_transient() && attempt < self.policy.retries => {
+                    let delay = backoff(self.policy.backoff, attempt);
+                    eprintln!("{}: {}, retrying in {:?}", url, kind, delay);
+                    delay_for(delay).await;
+                    attempt += 1;
+                }
+                result => {
+                    return result.map_err(|kind| Error {
+                        url: url.to_string(),
+                        kind,
+                    })
+                }
+            }
+        }
+    }
+
+    async fn try_get(&self, url: &str, validators: &Validators) -> Result<Option<Body>, ErrorKind> {
+        let policy = &self.policy;
+        let mut request = self.client.get(url);
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag.as_str());
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+        }
+        let mut response = timeout(policy.connect_timeout + policy.read_timeout, request.send())
+            .await
+            .map_err(|_| ErrorKind::Timeout)??;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(ErrorKind::Status(response.status()));
+        }
+        if matches!(response.content_length(), Some(len) if len > policy.max_bytes) {
+            return Err(ErrorKind::TooLarge(policy.max_bytes));
+        }
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        let content_type = header(CONTENT_TYPE);
+        let validators = Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        //Content-Lengthがなくても上限を超えた時点で受信をやめる
+        let mut bytes = Vec::new();
+        while let Some(chunk) = timeout(policy.read_timeout, response.chunk())
+            .await
+            .map_err(|_| ErrorKind::Timeout)??
+        {
+            if (bytes.len() + chunk.len()) as u64 > policy.max_bytes {
+                return Err(ErrorKind::TooLarge(policy.max_bytes));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(Some(Body {
+            content_type,
+            bytes,
+            validators,
+        }))
+    }
+}
+
+/*attempt回目(0始まり)のやり直しまでの時間 base*2^attemptの半分から全体までの間でばらつかせる*/
+fn backoff(base: Duration, attempt: u32) -> Duration {
+    let delay = base * 2u32.pow(attempt.min(16));
+    delay / 2 + delay.mul_f64(rand::random::<f64>() / 2.0)
+}
+
+/*受信した内容を形式を判別して読み込む*/
+fn parse(
+    s: &str,
+    content_type: Option<&str>,
+    url: &str,
+    max_items: Option<usize>,
+) -> Result<Feed, feed::Error> {
+    let format = match feed::sniff(content_type, url) {
+        Some(format) => format,
+        None => feed::detect(s)?,
+    };
+    Feed::with_format(s, format, max_items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /*接続のたびにリクエストに応じた応答を返すサーバーを起動し、urlと接続の回数を返す*/
+    async fn serve(respond: fn(&str) -> &'static str) -> (String, Arc<AtomicUsize>) {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/rss.xml", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0; 1024];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+                let _ = socket.write_all(respond(&request).as_bytes()).await;
+            }
+        });
+        (url, count)
+    }
+
+    fn policy() -> Policy {
+        Policy {
+            backoff: Duration::from_millis(1),
+            ..Policy::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_test() {
+        //サーバー側のエラーはやり直し、回数を使い切ったら応答のステータスを返す
+        let (url, count) =
+            serve(|_| "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n").await;
+        let e = Fetcher::with_policy(None, policy())
+            .fetch(&url, &Validators::default())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            e.kind,
+            ErrorKind::Status(StatusCode::SERVICE_UNAVAILABLE)
+        ));
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        //見つからないものはやり直さない
+        let (url, count) = serve(|_| "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").await;
+        let e = Fetcher::with_policy(None, policy())
+            .fetch(&url, &Validators::default())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(e.kind, ErrorKind::Status(StatusCode::NOT_FOUND)));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn too_large_test() {
+        let (url, _) =
+            serve(|_| "HTTP/1.1 200 OK\r\nContent-Length: 16\r\n\r\n0123456789abcdef").await;
+        let fetcher = Fetcher::with_policy(
+            None,
+            Policy {
+                max_bytes: 8,
+                ..policy()
+            },
+        );
+        let e = fetcher.download(&url).await.err().unwrap();
+        assert!(matches!(e.kind, ErrorKind::TooLarge(8)));
+
+        let fetcher = Fetcher::with_policy(None, policy());
+        assert_eq!(fetcher.download(&url).await.unwrap(), b"0123456789abcdef");
+    }
+
+    #[tokio::test]
+    async fn conditional_get_test() {
+        let (url, _) = serve(|request| {
+            if request.contains("if-none-match: \"v1\"") {
+                "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\n\r\n"
+            } else {
+                "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nLast-Modified: Tue, 10 Jun 2003 04:00:00 GMT\r\nContent-Length: 73\r\n\r\n<rss version=\"2.0\"><channel><item><title>a</title></item></channel></rss>"
+            }
+        })
+        .await;
+        let fetcher = Fetcher::with_policy(None, policy());
+        let validators = match fetcher.fetch(&url, &Validators::default()).await.unwrap() {
+            Fetched::Modified(feed, validators) => {
+                assert_eq!(feed.items[0].title, "a");
+                validators
+            }
+            Fetched::NotModified => panic!("first fetch must return the feed"),
+        };
+        assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(
+            validators.last_modified.as_deref(),
+            Some("Tue, 10 Jun 2003 04:00:00 GMT")
+        );
+        assert!(matches!(
+            fetcher.fetch(&url, &validators).await.unwrap(),
+            Fetched::NotModified
+        ));
+    }
+
+    #[test]
+    fn backoff_test() {
+        let base = Duration::from_millis(100);
+        for attempt in 0..4 {
+            let delay = backoff(base, attempt);
+            let full = base * 2u32.pow(attempt);
+            assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let url = "https://example.com/rss.xml";
+        let e = parse("<rss>\n<channel>\n</rss>", None, url, None)
+            .err()
+            .unwrap();
+        let e = Error {
+            url: url.to_string(),
+            kind: ErrorKind::Parse(e),
+        };
+        assert!(matches!(
+            &e.kind,
+            ErrorKind::Parse(feed::Error::Parse {
+                position: Some((3, _)),
+                ..
+            })
+        ));
+        assert!(!e.kind.is_transient());
+        assert!(e
+            .to_string()
+            .starts_with("https://example.com/rss.xml: line 3, column "));
+    }
+}
@@ -0,0 +1,222 @@
+/*
+ * 説明文に含まれるHTMLを画面に表示できるプレーンテキストに変換する
+ * 段落や改行にあたるタグは改行にし、それ以外のタグは取り除く
+ * 連続する空白はひとつにまとめる(全角スペースは字下げに使われるので残す)
+ */
+pub fn to_text(s: &str) -> String {
+    let mut text = Text::default();
+    push_html(&mut text, s);
+    text.finish()
+}
+
+/*HTMLのsをプレーンテキストにしてtextに追加する*/
+fn push_html(text: &mut Text, s: &str) {
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(after) = rest.strip_prefix("<![CDATA[") {
+                //CDATAはHTMLをエスケープせずに埋め込むのに使われるので、中身も同じようにタグや文字参照を変換する
+                let end = after.find("]]>").unwrap_or(after.len());
+                push_html(text, &after[..end]);
+                rest = after.get(end + 3..).unwrap_or("");
+                continue;
+            }
+            if let Some(after) = rest.strip_prefix("<!--") {
+                rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+                continue;
+            }
+            if let Some((name, closing, after)) = tag(rest) {
+                rest = after;
+                match name.as_str() {
+                    "script" | "style" if !closing => {
+                        //中身ごと読み飛ばす
+                        let close = format!("</{}", name);
+                        rest = rest
+                            .to_ascii_lowercase()
+                            .find(&close)
+                            .and_then(|end| rest[end..].find('>').map(|i| &rest[end + i + 1..]))
+                            .unwrap_or("");
+                    }
+                    "li" if !closing => {
+                        text.line_break();
+                        text.push('・');
+                    }
+                    name if name == "li" || is_block(name) => text.line_break(),
+                    _ => (),
+                }
+                continue;
+            }
+        } else if c == '&' {
+            if let Some((decoded, after)) = entity(rest) {
+                text.push(decoded);
+                rest = after;
+                continue;
+            }
+        }
+        text.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+}
+
+/*空白をまとめながら文字を追加していくバッファ*/
+#[derive(Default)]
+struct Text {
+    s: String,
+    space: bool, //次の文字の前に空白を入れるか
+}
+
+impl Text {
+    fn push(&mut self, c: char) {
+        if c.is_whitespace() && c != '\u{3000}' {
+            self.space = !self.s.is_empty() && !self.s.ends_with('\n');
+        } else {
+            if self.space {
+                self.s.push(' ');
+                self.space = false;
+            }
+            self.s.push(c);
+        }
+    }
+
+    fn line_break(&mut self) {
+        self.space = false;
+        if !self.s.is_empty() && !self.s.ends_with('\n') {
+            self.s.push('\n');
+        }
+    }
+
+    fn finish(self) -> String {
+        self.s.trim_end().to_string()
+    }
+}
+
+/*sの先頭がタグなら小文字にした要素名、閉じタグかどうか、タグの後ろの残りを返す*/
+fn tag(s: &str) -> Option<(String, bool, &str)> {
+    let body = s.strip_prefix('<')?;
+    let closing = body.starts_with('/');
+    let name_start = body.strip_prefix('/').unwrap_or(body);
+    let first = name_start.chars().next()?;
+    if !first.is_ascii_alphabetic() && first != '!' && first != '?' {
+        //"a < b"のような地の文の不等号
+        return None;
+    }
+    let end = body.find('>')?;
+    let name = name_start
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    Some((name, closing, &body[end + 1..]))
+}
+
+/*前後で改行する要素*/
+fn is_block(name: &str) -> bool {
+    matches!(
+        name,
+        "br" | "p"
+            | "div"
+            | "blockquote"
+            | "pre"
+            | "ul"
+            | "ol"
+            | "dl"
+            | "dt"
+            | "dd"
+            | "table"
+            | "tr"
+            | "hr"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "figure"
+            | "figcaption"
+    )
+}
+
+/*sの先頭が文字参照ならデコードした文字と残りを返す*/
+fn entity(s: &str) -> Option<(char, &str)> {
+    let end = s.get(..12).unwrap_or(s).find(';')?;
+    let name = &s[1..end];
+    let c = if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        std::char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+    } else if let Some(dec) = name.strip_prefix('#') {
+        std::char::from_u32(dec.parse().ok()?)?
+    } else {
+        named_entity(name)?
+    };
+    Some((c, &s[end + 1..]))
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "yen" => '¥',
+        "times" => '×',
+        "divide" => '÷',
+        "middot" => '·',
+        "bull" => '•',
+        "hellip" => '…',
+        "ndash" => '–',
+        "mdash" => '—',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "larr" => '←',
+        "rarr" => '→',
+        "uarr" => '↑',
+        "darr" => '↓',
+        _ => return None,
+    };
+    Some(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn to_text_test() {
+        assert_eq!(
+            to_text("  東京&amp;大阪の&#12354;&#x3042;  &quot;天気&quot;  "),
+            "東京&大阪のああ \"天気\""
+        );
+        assert_eq!(
+            to_text("<p>一段落目です。<br/>改行 しました。</p>\n\n<p>二段落目&hellip;</p>"),
+            "一段落目です。\n改行 しました。\n二段落目…"
+        );
+        assert_eq!(
+            to_text(
+                r#"詳しくは<a href="https://example.com/?a=1&amp;b=2">こちら</a>。<img src="x.png" />"#
+            ),
+            "詳しくはこちら。"
+        );
+        assert_eq!(
+            to_text("<ul>\n  <li>一つ目</li>\n  <li>二つ目</li>\n</ul>"),
+            "・一つ目\n・二つ目"
+        );
+        assert_eq!(
+            to_text("<![CDATA[<b>太字</b>]]>と<!-- コメント -->本文<script>alert('x')</script>"),
+            "太字と本文"
+        );
+        assert_eq!(
+            to_text("<![CDATA[<p>一行目</p><p>A&amp;B</p>]]>"),
+            "一行目\nA&B"
+        );
+        //タグに見えない不等号や知らない文字参照はそのまま残す
+        assert_eq!(to_text("1 < 2 &unknown; &"), "1 < 2 &unknown; &");
+        assert_eq!(to_text("\u{3000}字下げ"), "\u{3000}字下げ");
+    }
+}
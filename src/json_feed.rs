@@ -0,0 +1,215 @@
+use crate::date::PubDate;
+use crate::feed::{self, Feed, Schedule};
+use crate::html;
+use serde::Deserialize;
+use serde_json::Error;
+
+/*JSON Feed 1.1(1.0とも互換)*/
+#[derive(Deserialize)]
+pub struct JsonFeed {
+    pub version: String,
+    pub title: String,
+    pub home_page_url: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub items: Vec<Item>,
+}
+
+impl JsonFeed {
+    pub fn new(s: &str) -> Result<Self, Error> {
+        serde_json::from_str(s)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Item {
+    pub id: Option<Id>,
+    pub url: Option<String>,
+    pub external_url: Option<String>,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub content_text: Option<String>,
+    pub content_html: Option<String>,
+    pub date_published: Option<String>,
+    pub date_modified: Option<String>,
+    pub image: Option<String>,
+    pub banner_image: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<Author>,
+    pub author: Option<Author>, //1.0の形式
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/*idは文字列と決められているが、数値で書くフィードもあるのでどちらも受け付ける*/
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    String(String),
+    Number(serde_json::Number),
+}
+
+impl Id {
+    pub fn into_string(self) -> String {
+        match self {
+            Id::String(s) => s,
+            Id::Number(n) => n.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Author {
+    pub name: Option<String>,
+}
+
+impl From<JsonFeed> for Feed {
+    fn from(json: JsonFeed) -> Self {
+        Feed {
+            title: json.title,
+            link: json.home_page_url,
+            description: json.description,
+            items: json
+                .items
+                .into_iter()
+                .map(|item| {
+                    let content = item
+                        .content_text
+                        .clone()
+                        .or_else(|| item.content_html.as_deref().map(html::to_text));
+                    //タイトルのない短文の投稿は要約か本文をタイトルの代わりにする
+                    let title = item
+                        .title
+                        .clone()
+                        .or_else(|| item.summary.clone())
+                        .or_else(|| content.clone())
+                        .unwrap_or_default();
+                    feed::Item {
+                        title,
+                        link: item.url.or(item.external_url),
+                        guid: item.id.map(Id::into_string),
+                        pub_date: item
+                            .date_published
+                            .or(item.date_modified)
+                            .as_deref()
+                            .map(PubDate::parse),
+                        description: item.summary,
+                        content,
+                        image: item.image.or(item.banner_image),
+                        author: Some(
+                            item.authors
+                                .into_iter()
+                                .chain(item.author)
+                                .filter_map(|author| author.name)
+                                .collect::<Vec<_>>()
+                                .join("、"),
+                        )
+                        .filter(|names| !names.is_empty()),
+                        categories: item.tags,
+                    }
+                })
+                .collect(),
+            warnings: Vec::new(),
+            schedule: Schedule::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn make_json_feed_test() {
+        let s = r#"
+            {
+                "version": "https://jsonfeed.org/version/1.1",
+                "title": "社内ステータス",
+                "home_page_url": "https://status.example.com/",
+                "feed_url": "https://status.example.com/feed.json",
+                "authors": [{ "name": "運用チーム" }],
+                "items": [
+                    {
+                        "id": "2",
+                        "url": "https://status.example.com/incidents/2",
+                        "title": "APIの応答遅延",
+                        "summary": "一部のAPIで応答が遅延しています",
+                        "content_text": "現在調査中です。",
+                        "image": "https://status.example.com/2.png",
+                        "date_published": "2021-08-12T11:37:05+09:00",
+                        "tags": ["api"]
+                    },
+                    {
+                        "id": 1,
+                        "external_url": "https://example.com/maintenance",
+                        "content_html": "<p>定期メンテナンスのお知らせ</p>",
+                        "date_modified": "2021-08-10T09:00:00+09:00"
+                    }
+                ]
+            }
+        "#;
+        let feed = Feed::from(JsonFeed::new(s).unwrap());
+        assert_eq!(feed.title, "社内ステータス");
+        assert_eq!(feed.link.unwrap(), "https://status.example.com/");
+        assert_eq!(feed.items.len(), 2);
+
+        let item = feed.items.first().unwrap();
+        assert_eq!(item.title, "APIの応答遅延");
+        assert_eq!(item.guid.as_deref(), Some("2"));
+        assert_eq!(
+            item.link.as_ref().unwrap(),
+            "https://status.example.com/incidents/2"
+        );
+        assert_eq!(
+            item.pub_date.as_ref().unwrap().raw,
+            "2021-08-12T11:37:05+09:00"
+        );
+        assert_eq!(
+            item.description.as_ref().unwrap(),
+            "一部のAPIで応答が遅延しています"
+        );
+        assert_eq!(item.content.as_ref().unwrap(), "現在調査中です。");
+        assert_eq!(
+            item.image.as_ref().unwrap(),
+            "https://status.example.com/2.png"
+        );
+
+        let item = feed.items.get(1).unwrap();
+        assert_eq!(item.title, "定期メンテナンスのお知らせ");
+        assert_eq!(item.guid.as_deref(), Some("1"));
+        assert_eq!(
+            item.link.as_ref().unwrap(),
+            "https://example.com/maintenance"
+        );
+        assert_eq!(
+            item.pub_date.as_ref().unwrap().raw,
+            "2021-08-10T09:00:00+09:00"
+        );
+        assert_eq!(item.content.as_ref().unwrap(), "定期メンテナンスのお知らせ");
+
+        assert!(JsonFeed::new(r#"{"version": "1.1"}"#).is_err());
+    }
+
+    #[test]
+    fn title_fallback_test() {
+        //タイトルがなければ要約、テキストの本文、HTMLの本文の順に代わりにする
+        let s = r#"
+            {
+                "version": "https://jsonfeed.org/version/1.1",
+                "title": "つぶやき",
+                "items": [
+                    { "id": "1", "summary": "要約", "content_text": "本文" },
+                    { "id": "2", "content_text": "本文", "content_html": "<p>HTML</p>" },
+                    { "id": "3", "content_html": "<p>短い<b>投稿</b>です</p>" },
+                    { "id": "4" }
+                ]
+            }
+        "#;
+        let feed = Feed::from(JsonFeed::new(s).unwrap());
+        let titles = feed
+            .items
+            .iter()
+            .map(|item| item.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["要約", "本文", "短い投稿です", ""]);
+    }
+}
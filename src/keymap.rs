@@ -0,0 +1,285 @@
+use crate::app::State;
+use std::collections::HashMap;
+use std::fmt;
+
+/*ボタンの押し方 buttonsはF1,F2,F3のうち押されたもの longは長押しかどうか*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Gesture {
+    pub buttons: [bool; 3],
+    pub long: bool,
+}
+
+impl Gesture {
+    pub fn press(buttons: [bool; 3]) -> Self {
+        Self {
+            buttons,
+            long: false,
+        }
+    }
+
+    pub fn long_press(buttons: [bool; 3]) -> Self {
+        Self {
+            buttons,
+            long: true,
+        }
+    }
+
+    /*"f2"、"f1+f3"、"long_f2"のような名前から変換する*/
+    pub fn parse(s: &str) -> Option<Self> {
+        let (long, names) = match s.strip_prefix("long_") {
+            Some(names) => (true, names),
+            None => (false, s),
+        };
+        let mut buttons = [false; 3];
+        for name in names.split('+') {
+            let i = match name {
+                "f1" => 0,
+                "f2" => 1,
+                "f3" => 2,
+                _ => return None,
+            };
+            buttons[i] = true;
+        }
+        Some(Self { buttons, long })
+    }
+}
+
+impl fmt::Display for Gesture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = ["f1", "f2", "f3"]
+            .iter()
+            .zip(self.buttons.iter())
+            .filter(|(_, &pressed)| pressed)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        if self.long {
+            write!(f, "long_")?;
+        }
+        write!(f, "{}", names.join("+"))
+    }
+}
+
+/*ボタン操作に割り当てられる動作*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Down,     //選択を下に移動
+    Up,       //選択を上に移動
+    Select,   //決定
+    Back,     //ひとつ前の画面に戻る
+    Refresh,  //表示中のカテゴリを取得し直す
+    Home,     //カテゴリ一覧に戻る
+    Info,     //記事の著者やカテゴリを表示する
+    ReadAll,  //カテゴリの記事をすべて既読にする
+    Unread,   //記事を未読に戻す
+    Bookmark, //記事をブックマークする(してあれば外す)
+    Qr,       //記事のリンクをQRコードで表示する
+}
+
+impl Action {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "down" => Some(Action::Down),
+            "up" => Some(Action::Up),
+            "select" => Some(Action::Select),
+            "back" => Some(Action::Back),
+            "refresh" => Some(Action::Refresh),
+            "home" => Some(Action::Home),
+            "info" => Some(Action::Info),
+            "read_all" => Some(Action::ReadAll),
+            "unread" => Some(Action::Unread),
+            "bookmark" => Some(Action::Bookmark),
+            "qr" => Some(Action::Qr),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Action::Down => "down",
+            Action::Up => "up",
+            Action::Select => "select",
+            Action::Back => "back",
+            Action::Refresh => "refresh",
+            Action::Home => "home",
+            Action::Info => "info",
+            Action::ReadAll => "read_all",
+            Action::Unread => "unread",
+            Action::Bookmark => "bookmark",
+            Action::Qr => "qr",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/*画面ごとにボタン操作と動作を対応付ける構造体*/
+pub struct Keymap {
+    bindings: HashMap<(State, Gesture), Action>,
+}
+
+impl Default for Keymap {
+    /*
+     * F1で下、F3で上(本文ではページ送り)、F2で決定(本文では画像の表示)、F1+F3で戻る、本文でF2長押しで記事の情報
+     * 一覧でF2長押しですべて既読、タイトル一覧と本文でF1長押しで未読に戻し、F3長押しでブックマーク
+     * 本文でF1+F3長押しでリンクのQRコード
+     */
+    fn default() -> Self {
+        let f1 = Gesture::press([true, false, false]);
+        let f2 = Gesture::press([false, true, false]);
+        let f3 = Gesture::press([false, false, true]);
+        let f1_f3 = Gesture::press([true, false, true]);
+        let long_f1_f3 = Gesture::long_press([true, false, true]);
+        let long_f1 = Gesture::long_press([true, false, false]);
+        let long_f2 = Gesture::long_press([false, true, false]);
+        let long_f3 = Gesture::long_press([false, false, true]);
+        let bindings = [
+            (State::Category, f1, Action::Down),
+            (State::Category, f3, Action::Up),
+            (State::Category, f2, Action::Select),
+            (State::Category, long_f2, Action::ReadAll),
+            (State::Title, f1, Action::Down),
+            (State::Title, f3, Action::Up),
+            (State::Title, f2, Action::Select),
+            (State::Title, f1_f3, Action::Back),
+            (State::Title, long_f2, Action::ReadAll),
+            (State::Title, long_f1, Action::Unread),
+            (State::Title, long_f3, Action::Bookmark),
+            (State::Overview, f1, Action::Down),
+            (State::Overview, f3, Action::Up),
+            (State::Overview, f2, Action::Select),
+            (State::Overview, f1_f3, Action::Back),
+            (State::Image, f2, Action::Back),
+            (State::Image, f1_f3, Action::Back),
+            (State::Overview, long_f2, Action::Info),
+            (State::Overview, long_f1, Action::Unread),
+            (State::Overview, long_f3, Action::Bookmark),
+            (State::Overview, long_f1_f3, Action::Qr),
+            (State::Qr, f2, Action::Back),
+            (State::Qr, f1_f3, Action::Back),
+            (State::Info, f2, Action::Back),
+            (State::Info, f1_f3, Action::Back),
+            (State::Error, f2, Action::Back),
+            (State::Error, f1_f3, Action::Back),
+        ]
+        .iter()
+        .map(|&(state, gesture, action)| ((state, gesture), action))
+        .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /*
+     * 設定ファイルの[keymap.<画面>]の内容を既定の割り当てに上書きする
+     * 画面は"category"、"title"、"overview"、"image"、"info"、"qr"、"error"、"all"(全画面)のいずれかで、
+     * 値に"none"を指定するとその操作の割り当てを解除する
+     * "all"を先に適用し、画面ごとの指定はそれより優先する
+     */
+    pub fn from_config(config: &HashMap<String, HashMap<String, String>>) -> Result<Self, String> {
+        let mut keymap = Self::default();
+        let mut screens = config.iter().collect::<Vec<_>>();
+        screens.sort_by_key(|(screen, _)| screen.as_str() != "all");
+        for (screen, bindings) in screens {
+            let states = match screen.as_str() {
+                "category" => vec![State::Category],
+                "title" => vec![State::Title],
+                "overview" => vec![State::Overview],
+                "image" => vec![State::Image],
+                "info" => vec![State::Info],
+                "qr" => vec![State::Qr],
+                "error" => vec![State::Error],
+                "all" => vec![
+                    State::Category,
+                    State::Title,
+                    State::Overview,
+                    State::Image,
+                    State::Info,
+                    State::Qr,
+                    State::Error,
+                ],
+                _ => return Err(format!("unknown screen in keymap: {}", screen)),
+            };
+            for (gesture, action) in bindings {
+                let gesture = Gesture::parse(gesture)
+                    .ok_or_else(|| format!("unknown gesture in keymap: {}", gesture))?;
+                let action = match action.as_str() {
+                    "none" => None,
+                    _ => Some(
+                        Action::parse(action)
+                            .ok_or_else(|| format!("unknown action in keymap: {}", action))?,
+                    ),
+                };
+                for &state in &states {
+                    match action {
+                        Some(action) => keymap.bindings.insert((state, gesture), action),
+                        None => keymap.bindings.remove(&(state, gesture)),
+                    };
+                }
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn action(&self, state: State, gesture: Gesture) -> Option<Action> {
+        self.bindings.get(&(state, gesture)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn gesture_parse_test() {
+        assert_eq!(
+            Gesture::parse("f2"),
+            Some(Gesture::press([false, true, false]))
+        );
+        assert_eq!(
+            Gesture::parse("long_f1+f3"),
+            Some(Gesture::long_press([true, false, true]))
+        );
+        assert_eq!(Gesture::parse("f4"), None);
+        assert_eq!(Gesture::parse("long_"), None);
+        assert_eq!(
+            Gesture::long_press([true, false, true]).to_string(),
+            "long_f1+f3"
+        );
+    }
+
+    #[test]
+    fn from_config_test() {
+        let mut title = HashMap::new();
+        title.insert("f1".to_string(), "up".to_string());
+        title.insert("f3".to_string(), "down".to_string());
+        title.insert("f1+f3".to_string(), "none".to_string());
+        let mut all = HashMap::new();
+        all.insert("long_f2".to_string(), "home".to_string());
+        all.insert("f3".to_string(), "back".to_string());
+        let mut config = HashMap::new();
+        config.insert("title".to_string(), title);
+        config.insert("all".to_string(), all);
+        let keymap = Keymap::from_config(&config).unwrap();
+
+        let f1 = Gesture::press([true, false, false]);
+        let long_f2 = Gesture::long_press([false, true, false]);
+        let f1_f3 = Gesture::press([true, false, true]);
+        assert_eq!(keymap.action(State::Title, f1), Some(Action::Up));
+        assert_eq!(keymap.action(State::Title, f1_f3), None);
+        //同じ操作は"all"より画面ごとの指定が優先される
+        let f3 = Gesture::press([false, false, true]);
+        assert_eq!(keymap.action(State::Title, f3), Some(Action::Down));
+        assert_eq!(keymap.action(State::Overview, f3), Some(Action::Back));
+        assert_eq!(keymap.action(State::Category, f1), Some(Action::Down));
+        assert_eq!(keymap.action(State::Overview, f1_f3), Some(Action::Back));
+        assert_eq!(keymap.action(State::Overview, long_f2), Some(Action::Home));
+        assert_eq!(keymap.action(State::Image, long_f2), Some(Action::Home));
+        assert_eq!(keymap.action(State::Image, f1_f3), Some(Action::Back));
+
+        let mut bad = HashMap::new();
+        bad.insert("f1".to_string(), "jump".to_string());
+        let mut config = HashMap::new();
+        config.insert("title".to_string(), bad);
+        assert!(Keymap::from_config(&config).is_err());
+    }
+}
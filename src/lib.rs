@@ -0,0 +1,22 @@
+pub mod app;
+pub mod atom;
+pub mod bookmark;
+pub mod cache;
+pub mod charset;
+pub mod config;
+pub mod date;
+pub mod display;
+pub mod event;
+pub mod feed;
+pub mod fetch;
+pub mod html;
+pub mod json_feed;
+pub mod keymap;
+pub mod nanohat;
+pub mod rdf;
+pub mod read;
+pub mod record;
+pub mod remote;
+pub mod rss;
+pub mod scheduler;
+pub mod text;
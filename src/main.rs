@@ -1,395 +1,344 @@
-mod nanohat;
-mod rss;
-
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
 use i2cdev::linux::LinuxI2CDevice;
-use image::imageops::colorops::invert;
-use image::imageops::overlay;
-use image::{DynamicImage, GenericImage, GenericImageView, GrayImage, Luma};
-use imageproc::drawing::draw_text_mut;
-use rusttype::{Font, Scale};
+use nanohat_oled_rss_reader::app::App;
+use nanohat_oled_rss_reader::cache::Cache;
+use nanohat_oled_rss_reader::config::Config;
+use nanohat_oled_rss_reader::display::{Display, SimulatedDisplay};
+use nanohat_oled_rss_reader::event::Event;
+use nanohat_oled_rss_reader::fetch::{self, Fetched, Fetcher, Request};
+use nanohat_oled_rss_reader::keymap::Keymap;
+use nanohat_oled_rss_reader::nanohat;
+use nanohat_oled_rss_reader::record::{Outcome, Record, Recorder, Replayer};
+use nanohat_oled_rss_reader::remote;
+use nanohat_oled_rss_reader::scheduler::Scheduler;
+use rusttype::Font;
 use std::error::Error;
-use std::ops::Range;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
 
-/*カテゴリ表示画面に必要な情報を保持する構造体*/
-struct CategoryPane {
-    categories: Vec<GrayImage>, //カテゴリの文字を画像化したもののリスト
-    urls: Vec<&'static str>,    //データ取得先のurlリスト
-    start_i: usize,             //表示領域の最初のインデックス
-    selected: usize,            //画面上で選択されているインデックス(0 <= x < 8 )
-}
+const TICK_INTERVAL: Duration = Duration::from_secs(30); //フィードを取得し直す時期かを確かめる間隔
 
-/*タイトル表示画面に必要な情報を保持する構造体*/
-struct TitlePane {
-    titles: Vec<GrayImage>,    //カテゴリの文字を画像化したもののリスト
-    descriptions: Vec<String>, //タイトルの説明を保持するリスト
-    start_i: usize,            //表示領域の最初のインデックス
-    selected: usize,           //画面上で選択されているインデックス(0 <= x < 8 )
+/*コマンドライン引数で指定できる起動オプション*/
+#[derive(Default)]
+struct Options {
+    config: Option<PathBuf>,   //設定ファイル
+    record: Option<PathBuf>,   //ボタン入力を記録するファイル
+    replay: Option<PathBuf>,   //ボタン入力の代わりに再生する記録ファイル
+    simulate: Option<PathBuf>, //OLEDの代わりに画面をPNGで保存するディレクトリ
+    socket: Option<PathBuf>,   //リモート操作を受け付けるUNIXドメインソケット
+    tcp: Option<String>,       //リモート操作を受け付けるTCPのアドレス
 }
-/*状態を表す列挙型*/
-enum State {
-    Category,
-    Title,
-    Overview,
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut options = Self::default();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("{} requires a value", arg))
+            };
+            match arg.as_str() {
+                "--config" => options.config = Some(PathBuf::from(value()?)),
+                "--record" => options.record = Some(PathBuf::from(value()?)),
+                "--replay" => options.replay = Some(PathBuf::from(value()?)),
+                "--simulate" => options.simulate = Some(PathBuf::from(value()?)),
+                "--socket" => options.socket = Some(PathBuf::from(value()?)),
+                "--tcp" => options.tcp = Some(value()?),
+                _ => return Err(format!("unknown option: {}", arg)),
+            }
+        }
+        Ok(options)
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let i2cdev = LinuxI2CDevice::new("/dev/i2c-0", 0x3c)?;
-    let mut oled = nanohat::oled::NanoHatOLED::open(i2cdev)?;
-    let mut button = nanohat::button::Button::open("/dev/gpiochip0")?;
+    let options = Options::parse(std::env::args().skip(1))?;
+    let config = match &options.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let keymap = Keymap::from_config(&config.keymap)?;
+
     let font = {
         let v = Vec::from(include_bytes!("font/misaki_gothic.ttf") as &[u8]);
         Font::try_from_vec(v).unwrap()
     };
 
-    let categories = [
-        "主要",
-        "国内",
-        "国際",
-        "経済",
-        "エンタメ",
-        "スポーツ",
-        "IT",
-        "科学",
-        "地域",
-    ]
-    .iter()
-    .map(|category| {
-        let mut img = GrayImage::new(128, 8);
-        draw_text_mut(
-            &mut img,
-            Luma([255]),
-            0,
-            0,
-            Scale { x: 8.0, y: 8.0 },
-            &font,
-            category,
-        ); //画像にカテゴリの文字を描画
-        img
-    })
-    .collect::<Vec<_>>();
-    let urls = vec![
-        "https://news.yahoo.co.jp/rss/topics/top-picks.xml",
-        "https://news.yahoo.co.jp/rss/topics/domestic.xml",
-        "https://news.yahoo.co.jp/rss/topics/world.xml",
-        "https://news.yahoo.co.jp/rss/topics/business.xml",
-        "https://news.yahoo.co.jp/rss/topics/entertainment.xml",
-        "https://news.yahoo.co.jp/rss/topics/sports.xml",
-        "https://news.yahoo.co.jp/rss/topics/it.xml",
-        "https://news.yahoo.co.jp/rss/topics/science.xml",
-        "https://news.yahoo.co.jp/rss/topics/local.xml",
+    let defaults = [
+        ("主要", "https://news.yahoo.co.jp/rss/topics/top-picks.xml"),
+        ("国内", "https://news.yahoo.co.jp/rss/topics/domestic.xml"),
+        ("国際", "https://news.yahoo.co.jp/rss/topics/world.xml"),
+        ("経済", "https://news.yahoo.co.jp/rss/topics/business.xml"),
+        (
+            "エンタメ",
+            "https://news.yahoo.co.jp/rss/topics/entertainment.xml",
+        ),
+        ("スポーツ", "https://news.yahoo.co.jp/rss/topics/sports.xml"),
+        ("IT", "https://news.yahoo.co.jp/rss/topics/it.xml"),
+        ("科学", "https://news.yahoo.co.jp/rss/topics/science.xml"),
+        ("地域", "https://news.yahoo.co.jp/rss/topics/local.xml"),
     ];
-    assert_eq!(categories.len(), urls.len());
+    let categories = config.categories(&defaults);
+    let feeds = categories
+        .iter()
+        .map(|(name, url)| (name.as_str(), url.as_str()))
+        .collect::<Vec<_>>();
 
-    /*最初に表示する画面を生成*/
-    let img = categories.iter().take(8).enumerate().fold(
-        GrayImage::new(128, 64),
-        |mut img, (i, page)| {
-            if i == 0 {
-                let mut inverted = page.clone();
-                invert(&mut inverted);
-                overlay(&mut img, &inverted, 0, (i * 8) as u32);
-            } else {
-                overlay(&mut img, page, 0, (i * 8) as u32);
+    /*ボタン入力または記録ファイルの再生とリモート操作をひとつのイベント列にまとめる*/
+    let (tx, rx) = mpsc::channel(16);
+    if let Some(path) = options.socket {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = remote::serve_unix(&path, tx).await {
+                eprintln!("remote control on {:?} stopped: {}", path, e);
             }
-            img
-        },
-    );
-    oled.draw_image(&img, 0, 0)?;
-
-    let mut category_pane = CategoryPane {
-        categories: categories,
-        urls: urls,
-        start_i: 0,
-        selected: 0,
-    };
+        });
+    }
+    if let Some(addr) = options.tcp {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = remote::serve_tcp(&addr, tx).await {
+                eprintln!("remote control on {} stopped: {}", addr, e);
+            }
+        });
+    }
 
-    let mut title_pane = TitlePane {
-        titles: Vec::new(),
-        descriptions: Vec::new(),
-        start_i: 0,
-        selected: 0,
+    /*フィードの定期的な取得 記録の再生は毎回同じ結果になるよう定期的な取得をしない*/
+    let scheduler = Scheduler::new(
+        feeds
+            .iter()
+            .map(|(category, _)| config.min_interval(category))
+            .collect(),
+    );
+    if options.replay.is_none() {
+        let mut tx = tx.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if tx.send(Event::Tick).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    //記録の再生では先読みの結果も記録したものを使う
+    let background = match options.replay {
+        Some(_) => None,
+        None => Some(tx.clone()),
     };
-
-    let mut state = State::Category;
-
-    while let Ok(pressed) = button.pressed().await {
-        match state {
-            State::Category => match pressed {
-                [true, false, false] => {
-                    //下に移動
-                    if category_pane.selected < 7 {
-                        //反転している箇所を下に移動するだけで良い場合
-                        let mut i = category_pane.start_i + category_pane.selected; //現在選択中のカテゴリのインデックス算出
-                        let img = category_pane.categories.get(i).unwrap(); //選択中の画像取得
-                        oled.draw_image(img, 0, category_pane.selected as u8)?; //反転していた表示をもとの表示に戻す
-                        category_pane.selected += 1;
-                        i += 1;
-                        let mut img = category_pane.categories.get(i).unwrap().clone(); //移動先の画像取得
-                        invert(&mut img); //画像を反転
-                        oled.draw_image(&img, 0, category_pane.selected as u8)?;
-                    //反転した画像を表示
-                    } else if category_pane.categories.len() > category_pane.start_i + 8 {
-                        //一番下を選択している状態で下に移動する場合
-                        category_pane.start_i += 1;
-                        let img = category_pane
-                            .categories
-                            .iter()
-                            .skip(category_pane.start_i)
-                            .enumerate()
-                            .fold(GrayImage::new(128, 64), |mut img, (i, page)| {
-                                if i == 7 {
-                                    //一番下は選択したままなので反転する
-                                    let mut inverted = page.clone();
-                                    invert(&mut inverted);
-                                    overlay(&mut img, &inverted, 0, (i * 8) as u32);
-                                } else {
-                                    overlay(&mut img, page, 0, (i * 8) as u32);
-                                }
-                                img
-                            });
-                        oled.draw_image(&img, 0, 0)?;
+    let mut tx = tx;
+    match &options.replay {
+        Some(path) => {
+            let mut replayer = Replayer::open(path)?;
+            let urls = feeds
+                .iter()
+                .map(|(_, url)| url.to_string())
+                .collect::<Vec<_>>();
+            tokio::spawn(async move {
+                while let Some(record) = replayer.event().await {
+                    let event = match record {
+                        Record::Gesture(gesture) => Event::Gesture(gesture),
+                        //リモート操作の応答は捨てる
+                        Record::Remote(command) => Event::Remote(command, oneshot::channel().0),
+                        Record::Fetched(i, outcome) => Event::Replayed(i, outcome),
+                        Record::Prefetched(i, _) | Record::Refreshed(i, _) if i >= urls.len() => {
+                            eprintln!("category {} in the record does not exist", i);
+                            continue;
+                        }
+                        Record::Prefetched(i, outcome) => {
+                            Event::Prefetched(i, Utc::now(), outcome.into_result(&urls[i]))
+                        }
+                        Record::Refreshed(i, outcome) => {
+                            Event::Refreshed(i, Utc::now(), outcome.into_result(&urls[i]))
+                        }
+                    };
+                    if tx.send(event).await.is_err() {
+                        break;
                     }
                 }
-                [false, false, true] => {
-                    //上に移動
-                    if category_pane.selected > 0 {
-                        //反転している箇所を上に移動するだけで良い場合
-                        let mut i = category_pane.start_i + category_pane.selected; //現在選択中のカテゴリのインデックス算出
-                        let img = category_pane.categories.get(i).unwrap(); //選択中の画像取得
-                        oled.draw_image(img, 0, category_pane.selected as u8)?; //反転していた表示をもとの表示に戻す
-                        category_pane.selected -= 1;
-                        i -= 1;
-                        let mut img = category_pane.categories.get(i).unwrap().clone(); //移動先の画像取得
-                        invert(&mut img); //画像を反転
-                        oled.draw_image(&img, 0, category_pane.selected as u8)?;
-                    //反転した画像を表示
-                    } else if category_pane.start_i > 0 {
-                        //一番上を選択している状態で上に移動する場合
-                        category_pane.start_i -= 1;
-                        let img = category_pane
-                            .categories
-                            .iter()
-                            .skip(category_pane.start_i)
-                            .enumerate()
-                            .fold(GrayImage::new(128, 64), |mut img, (i, page)| {
-                                if i == 0 {
-                                    //一番上は選択したままなので反転する
-                                    let mut inverted = page.clone();
-                                    invert(&mut inverted);
-                                    overlay(&mut img, &inverted, 0, (i * 8) as u32);
-                                } else {
-                                    overlay(&mut img, page, 0, (i * 8) as u32);
-                                }
-                                img
-                            });
-                        oled.draw_image(&img, 0, 0)?;
+            });
+        }
+        None => {
+            let mut button = nanohat::button::Button::open("/dev/gpiochip0")?;
+            let long_press = Duration::from_millis(config.long_press_ms);
+            tokio::spawn(async move {
+                while let Ok(gesture) = button.gesture(long_press).await {
+                    if tx.send(Event::Gesture(gesture)).await.is_err() {
+                        break;
                     }
                 }
-                [false, true, false] => {
-                    //選択したカテゴリのタイトル一覧画面へ遷移
-                    let i = category_pane.start_i + category_pane.selected;
-                    let url = category_pane.urls.get(i).unwrap();
-                    let s = reqwest::get(*url).await?.text().await?;
-                    let rss = rss::RSS::new(&s)?;
+            });
+        }
+    }
 
-                    let titles = rss
-                        .channel
-                        .items
-                        .iter()
-                        .map(|item| {
-                            let mut img = GrayImage::new(128, 8);
-                            draw_text_mut(
-                                &mut img,
-                                Luma([255]),
-                                0,
-                                0,
-                                Scale { x: 8.0, y: 8.0 },
-                                &font,
-                                &item.title,
-                            );
-                            img
-                        })
-                        .collect::<Vec<_>>();
+    let fetcher = Fetcher::with_policy(Some(config.max_items), config.fetch_policy());
+    //記録の再生は保存してあるフィードや既読の記録を読み書きせず、何度再生しても同じ状態から始める
+    let cache = match options.replay {
+        Some(_) => None,
+        None => Some(Cache::with_data_dir(&config.cache_dir, &config.data_dir)),
+    };
+    let background = background.map(|tx| (fetcher.clone(), config.prefetch_concurrency.max(1), tx));
 
-                    let descriptions = rss
-                        .channel
-                        .items
-                        .into_iter()
-                        .map(|item| {
-                            match item.description {
-                                Some(s) => s,
-                                None => "Not Found".to_string(),
-                            }
-                        })
-                        .collect::<Vec<_>>();
+    let recorder = match &options.record {
+        Some(path) => Some(Recorder::create(path)?),
+        None => None,
+    };
 
-                    let img = titles.iter().take(8).enumerate().fold(
-                        GrayImage::new(128, 64),
-                        |mut img, (i, page)| {
-                            if i == 0 {
-                                let mut inverted = page.clone();
-                                invert(&mut inverted);
-                                overlay(&mut img, &inverted, 0, (i * 8) as u32);
-                            } else {
-                                overlay(&mut img, page, 0, (i * 8) as u32);
-                            }
-                            img
-                        },
-                    );
-                    oled.draw_image(&img, 0, 0)?;
-                    state = State::Title;
-                    title_pane = TitlePane {
-                        titles: titles,
-                        descriptions: descriptions,
-                        start_i: 0,
-                        selected: 0,
-                    };
+    match options.simulate {
+        Some(dir) => {
+            let display = SimulatedDisplay::new(Some(dir));
+            run(
+                App::new(display, font, &feeds, keymap, scheduler, fetcher, cache)?,
+                rx,
+                recorder,
+                background,
+                options.replay.is_some(),
+            )
+            .await
+        }
+        None => {
+            let i2cdev = LinuxI2CDevice::new("/dev/i2c-0", 0x3c)?;
+            let oled = nanohat::oled::NanoHatOLED::open(i2cdev)?;
+            run(
+                App::new(oled, font, &feeds, keymap, scheduler, fetcher, cache)?,
+                rx,
+                recorder,
+                background,
+                options.replay.is_some(),
+            )
+            .await
+        }
+    }
+}
+
+/*
+ * 入力が尽きるまでイベントを画面に反映する
+ * backgroundを指定すると、最初にすべてのフィードを指定した数ずつ並行して取得し始め、
+ * その後も取得し直す時期になったフィードを裏で取得する 取得している間もボタン操作を受け付ける
+ * recorderを指定すると、入力と取得の結果を記録する
+ * replayなら通信せず、記録の再生から届く取得の結果を使う
+ */
+async fn run<D: Display>(
+    mut app: App<D>,
+    mut rx: mpsc::Receiver<Event>,
+    mut recorder: Option<Recorder>,
+    background: Option<(Fetcher, usize, mpsc::Sender<Event>)>,
+    replay: bool,
+) -> Result<(), Box<dyn Error>> {
+    if recorder.is_some() {
+        app.record_fetches();
+    }
+    if replay {
+        app.replay_fetches();
+    }
+    //記録の再生でも先読みの結果は届くので、進み具合の表示は始める
+    let requests = app.start_prefetch()?;
+    if let Some((fetcher, concurrency, tx)) = &background {
+        let (fetcher, tx) = (fetcher.clone(), tx.clone());
+        tokio::spawn(fetch_all(
+            fetcher,
+            requests,
+            *concurrency,
+            tx,
+            Event::Prefetched,
+        ));
+    }
+    while let Some(event) = rx.recv().await {
+        match event {
+            Event::Gesture(gesture) => {
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(&Record::Gesture(gesture))?;
                 }
-                _ => (),
-            },
-            State::Title => match pressed {
-                [true, false, false] => {
-                    if title_pane.selected < 7 {
-                        let mut i = title_pane.start_i + title_pane.selected;
-                        let img = title_pane.titles.get(i).unwrap();
-                        oled.draw_image(img, 0, title_pane.selected as u8)?;
-                        title_pane.selected += 1;
-                        i += 1;
-                        let mut img = title_pane.titles.get(i).unwrap().clone();
-                        invert(&mut img);
-                        oled.draw_image(&img, 0, title_pane.selected as u8)?;
-                    } else if title_pane.titles.len() > title_pane.start_i + 8 {
-                        title_pane.start_i += 1;
-                        let img = title_pane
-                            .titles
-                            .iter()
-                            .skip(title_pane.start_i)
-                            .enumerate()
-                            .fold(GrayImage::new(128, 64), |mut img, (i, page)| {
-                                if i == 7 {
-                                    let mut inverted = page.clone();
-                                    invert(&mut inverted);
-                                    overlay(&mut img, &inverted, 0, (i * 8) as u32);
-                                } else {
-                                    overlay(&mut img, page, 0, (i * 8) as u32);
-                                }
-                                img
-                            });
-                        oled.draw_image(&img, 0, 0)?;
-                    }
+                //画面の遷移に失敗しても終了せず、次の入力を待つ
+                if let Err(e) = app.gesture(gesture).await {
+                    eprintln!("{}", e);
+                }
+                record_fetched(&mut app, &mut recorder)?;
+            }
+            Event::Remote(command, reply) => {
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(&Record::Remote(command.clone()))?;
                 }
-                [false, false, true] => {
-                    if title_pane.selected > 0 {
-                        let mut i = title_pane.start_i + title_pane.selected;
-                        let img = title_pane.titles.get(i).unwrap();
-                        oled.draw_image(img, 0, title_pane.selected as u8)?;
-                        title_pane.selected -= 1;
-                        i -= 1;
-                        let mut img = title_pane.titles.get(i).unwrap().clone();
-                        invert(&mut img);
-                        oled.draw_image(&img, 0, title_pane.selected as u8)?;
-                    } else if title_pane.start_i > 0 {
-                        title_pane.start_i -= 1;
-                        let img = title_pane
-                            .titles
-                            .iter()
-                            .skip(title_pane.start_i)
-                            .enumerate()
-                            .fold(GrayImage::new(128, 64), |mut img, (i, page)| {
-                                if i == 0 {
-                                    let mut inverted = page.clone();
-                                    invert(&mut inverted);
-                                    overlay(&mut img, &inverted, 0, (i * 8) as u32);
-                                } else {
-                                    overlay(&mut img, page, 0, (i * 8) as u32);
-                                }
-                                img
-                            });
-                        oled.draw_image(&img, 0, 0)?;
+                //リモート操作の失敗は応答で伝えるだけにして終了はしない
+                let result = match app.command(&command).await {
+                    Ok(()) => Ok(app.screen()),
+                    Err(e) => Err(e.to_string()),
+                };
+                let _ = reply.send(result);
+                record_fetched(&mut app, &mut recorder)?;
+            }
+            Event::Tick => {
+                if let Some((fetcher, concurrency, tx)) = &background {
+                    let requests = app.tick();
+                    if !requests.is_empty() {
+                        let (fetcher, tx) = (fetcher.clone(), tx.clone());
+                        tokio::spawn(fetch_all(
+                            fetcher,
+                            requests,
+                            *concurrency,
+                            tx,
+                            Event::Refreshed,
+                        ));
                     }
                 }
-                [true, false, true] => {
-                    let img = category_pane
-                        .categories
-                        .iter()
-                        .skip(category_pane.start_i)
-                        .enumerate()
-                        .fold(GrayImage::new(128, 64), |mut img, (i, page)| {
-                            if i == category_pane.selected {
-                                let mut inverted = page.clone();
-                                invert(&mut inverted);
-                                overlay(&mut img, &inverted, 0, (i * 8) as u32);
-                            } else {
-                                overlay(&mut img, page, 0, (i * 8) as u32);
-                            }
-                            img
-                        });
-                    oled.draw_image(&img, 0, 0)?;
-                    state = State::Category;
+            }
+            Event::Prefetched(i, fetched_at, result) => {
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(&Record::Prefetched(i, Outcome::new(&result)))?;
                 }
-                [false, true, false] => {
-                    let i = title_pane.start_i + title_pane.selected;
-                    let s = title_pane.descriptions.get(i).unwrap();
-
-                    let (mut v, s, _) = s.chars().fold(
-                        (Vec::new(), String::new(), 0),
-                        |(mut v, mut s, mut column_count), c| {
-                            let width = if c.is_ascii() { 4 } else { 8 };
-                            if column_count + width > 128 {
-                                v.push(s);
-                                s = String::new();
-                                s.push(c);
-                                column_count = width;
-                            } else {
-                                s.push(c);
-                                column_count += width;
-                            }
-                            (v, s, column_count)
-                        },
-                    );
-                    v.push(s);
-                    let mut img = GrayImage::new(128, 64);
-                    for (i, s) in v.into_iter().enumerate() {
-                        draw_text_mut(
-                            &mut img,
-                            Luma([255]),
-                            0,
-                            (i * 8) as u32,
-                            Scale { x: 8.0, y: 8.0 },
-                            &font,
-                            &s,
-                        );
-                    }
-                    oled.draw_image(&img, 0, 0)?;
-                    state = State::Overview;
+                if let Err(e) = app.prefetched(i, fetched_at, result) {
+                    eprintln!("prefetch failed: {}", e);
+                }
+            }
+            Event::Refreshed(i, fetched_at, result) => {
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(&Record::Refreshed(i, Outcome::new(&result)))?;
                 }
-                _ => (),
-            },
-            State::Overview => match pressed {
-                [true, false, true] => {
-                    let img = title_pane
-                        .titles
-                        .iter()
-                        .skip(title_pane.start_i)
-                        .take(8)
-                        .enumerate()
-                        .fold(GrayImage::new(128, 64), |mut img, (i, page)| {
-                            if i == title_pane.selected {
-                                let mut inverted = page.clone();
-                                invert(&mut inverted);
-                                overlay(&mut img, &inverted, 0, (i * 8) as u32);
-                            } else {
-                                overlay(&mut img, page, 0, (i * 8) as u32);
-                            }
-                            img
-                        });
-                    oled.draw_image(&img, 0, 0)?;
-                    state = State::Title;
+                if let Err(e) = app.refreshed(i, fetched_at, result) {
+                    eprintln!("refresh failed: {}", e);
                 }
-                _ => (),
-            },
+            }
+            Event::Replayed(i, outcome) => app.replay_fetched(i, outcome),
         }
     }
     Ok(())
 }
+
+/*入力の処理の中で取得した結果を記録する*/
+fn record_fetched<D: Display>(app: &mut App<D>, recorder: &mut Option<Recorder>) -> io::Result<()> {
+    let fetched = app.take_fetched();
+    if let Some(recorder) = recorder {
+        for (i, outcome) in fetched {
+            recorder.record(&Record::Fetched(i, outcome))?;
+        }
+    }
+    Ok(())
+}
+
+/*カテゴリのフィードをconcurrencyずつ並行して取得し、取得できた順に結果をeventにして送る*/
+async fn fetch_all(
+    fetcher: Fetcher,
+    requests: Vec<Request>,
+    concurrency: usize,
+    mut tx: mpsc::Sender<Event>,
+    event: fn(usize, DateTime<Utc>, Result<Fetched, fetch::Error>) -> Event,
+) {
+    let fetcher = &fetcher;
+    let mut results = stream::iter(requests)
+        .map(|request| async move {
+            let fetched_at = Utc::now();
+            let result = fetcher.fetch(&request.url, &request.validators).await;
+            event(request.index, fetched_at, result)
+        })
+        .buffer_unordered(concurrency);
+    while let Some(event) = results.next().await {
+        if tx.send(event).await.is_err() {
+            break;
+        }
+    }
+}
@@ -1,11 +1,16 @@
+use crate::keymap::Gesture;
 use futures::stream::StreamExt;
-use gpio_cdev::{AsyncLineEventHandle, Chip, Error, EventRequestFlags, LineRequestFlags};
+use gpio_cdev::{
+    AsyncLineEventHandle, Chip, Error, EventRequestFlags, EventType, LineRequestFlags,
+};
 use std::path::Path;
+use std::time::Duration;
 
 pub struct Button {
     f1_handle: AsyncLineEventHandle,
     f2_handle: AsyncLineEventHandle,
     f3_handle: AsyncLineEventHandle,
+    held: [bool; 3], //長押しと判定した後もまだ押されているボタン
 }
 impl Button {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
@@ -18,7 +23,7 @@ impl Button {
             .map(|i| {
                 lines[i].events(
                     LineRequestFlags::INPUT,
-                    EventRequestFlags::RISING_EDGE,
+                    EventRequestFlags::BOTH_EDGES,
                     "PressEvent",
                 )
             })
@@ -26,7 +31,7 @@ impl Button {
 
         let mut async_event_handles = event_handles
             .into_iter()
-            .map(|handle| AsyncLineEventHandle::new(handle))
+            .map(AsyncLineEventHandle::new)
             .collect::<Result<Vec<_>, Error>>()?;
 
         assert_eq!(async_event_handles.len(), 3);
@@ -35,19 +40,71 @@ impl Button {
             f3_handle: async_event_handles.pop().unwrap(),
             f2_handle: async_event_handles.pop().unwrap(),
             f1_handle: async_event_handles.pop().unwrap(),
+            held: [false; 3],
         })
     }
-    pub async fn pressed(&mut self) -> Result<[bool; 3], Error> {
-        let pressed_button = tokio::select! {
-            _ = self.f1_handle.next() => [true, false, false],
-            _ = self.f2_handle.next() => [false, true, false],
-            _ = self.f3_handle.next() => [false, false, true],
+    /*
+     * ボタンが押されるまで待ち、押し方を返す
+     * 最初のボタンが押されてからすべて離されるまでに押されたボタンを同時押しとし、
+     * long_press以上離されなければ長押しとする
+     */
+    pub async fn gesture(&mut self, long_press: Duration) -> Result<Gesture, Error> {
+        //前回長押しと判定したボタンが離されるまでのイベントは読み捨てる
+        while self.held.iter().any(|&held| held) {
+            if let Some((i, false)) = self.next_edge().await? {
+                self.held[i] = false;
+            }
+        }
+        let mut buttons = [false; 3];
+        loop {
+            if let Some((i, true)) = self.next_edge().await? {
+                buttons[i] = true;
+                break;
+            }
+        }
+        //少し遅れて押されたボタンも同時押しに含める
+        let mut down = buttons;
+        let released = tokio::time::timeout(long_press, async {
+            while down.iter().any(|&down| down) {
+                if let Some((i, pressed)) = self.next_edge().await? {
+                    buttons[i] |= pressed;
+                    down[i] = pressed;
+                }
+            }
+            Ok::<_, Error>(())
+        })
+        .await;
+
+        match released {
+            Ok(result) => {
+                result?;
+                //チャタリングなどで溜まっているイベントを読み捨て、次の押し方に混ぜない
+                while let Ok(edge) =
+                    tokio::time::timeout(Duration::from_millis(0), self.next_edge()).await
+                {
+                    edge?;
+                }
+                Ok(Gesture::press(buttons))
+            }
+            Err(_) => {
+                self.held = down;
+                Ok(Gesture::long_press(buttons))
+            }
+        }
+    }
+
+    /*いずれかのボタンが押されるか離されるまで待ち、ボタンの番号と押されたかどうかを返す*/
+    async fn next_edge(&mut self) -> Result<Option<(usize, bool)>, Error> {
+        let (i, event) = tokio::select! {
+            event = self.f1_handle.next() => (0, event),
+            event = self.f2_handle.next() => (1, event),
+            event = self.f3_handle.next() => (2, event),
         };
-        Ok([
-            (self.f1_handle.as_ref().get_value()? == 1) | pressed_button[0],
-            (self.f2_handle.as_ref().get_value()? == 1) | pressed_button[1],
-            (self.f3_handle.as_ref().get_value()? == 1) | pressed_button[2],
-        ])
+        match event {
+            Some(Ok(event)) => Ok(Some((i, event.event_type() == EventType::RisingEdge))),
+            Some(Err(e)) => Err(e),
+            None => Ok(None),
+        }
     }
 }
 
@@ -57,7 +114,7 @@ mod tests {
     use super::*;
     #[tokio::test]
     async fn open_test() {
-        let button = Button::open("");
+        let _button = Button::open("");
         assert!(Button::open("").is_err());
         assert!(Button::open("/dev/gpiochip0").is_ok());
     }
@@ -1,6 +1,6 @@
 use i2cdev::core::I2CDevice;
 use image::imageops::{dither, BiLevel};
-use image::{DynamicImage, GenericImageView, GrayImage};
+use image::GrayImage;
 use std::error::Error;
 use std::io;
 
@@ -97,11 +97,11 @@ where
         let data = height_pixel_offsets
             .iter()
             .take(h as usize)
-            .map(|offset| {
+            .flat_map(|offset| {
                 (0..w)
                     .map(|x| {
                         (0..8).rev().fold(0u8, |mut bits, y| {
-                            let px = img.get_pixel(x as u32, offset + y);
+                            let px = img.get_pixel(x, offset + y);
                             bits <<= 1;
                             bits |= if px[0] == 255 { 1 } else { 0 };
                             bits
@@ -109,7 +109,6 @@ where
                     })
                     .collect::<Vec<_>>()
             })
-            .flat_map(|v| v)
             .collect::<Vec<_>>();
         self.set_draw_range(x, page_y, w as u8, h as u8)?;
         Self::send_data(&mut self.i2cdev, &data)?;
@@ -117,11 +116,7 @@ where
     }
 
     pub fn clear(&mut self, x: u8, y: u8, w: u8, h: u8) -> Result<(), Box<dyn Error>> {
-        if x + w > 128 {
-            return Err(
-                io::Error::new(io::ErrorKind::InvalidInput, "The range is too large").into(),
-            );
-        } else if y + h > 8 {
+        if x + w > 128 || y + h > 8 {
             return Err(
                 io::Error::new(io::ErrorKind::InvalidInput, "The range is too large").into(),
             );
@@ -0,0 +1,293 @@
+use crate::date::PubDate;
+use crate::feed::{self, Feed, Schedule};
+use crate::html;
+use std::mem;
+use xml::name::OwnedName;
+use xml::reader::{Error, ParserConfig, XmlEvent};
+
+const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const RSS1_NS: &str = "http://purl.org/rss/1.0/";
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+const CONTENT_NS: &str = "http://purl.org/rss/1.0/modules/content/";
+
+/*
+ * RSS 1.0(RDF)のフィード
+ * itemはchannelの子ではなく兄弟として<rdf:RDF>の直下に並ぶ
+ * 要素は接頭辞ではなく名前空間で判別する
+ */
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Default)]
+pub struct RDF {
+    pub channel: Channel,
+    pub items: Vec<Item>,
+}
+
+#[derive(Default)]
+pub struct Channel {
+    pub title: String,
+    pub link: Option<String>,
+    pub description: Option<String>,
+    pub date: Option<String>, //dc:date
+}
+
+#[derive(Default)]
+pub struct Item {
+    pub about: Option<String>, //rdf:about
+    pub title: String,
+    pub link: Option<String>,
+    pub description: Option<String>,
+    pub date: Option<String>,    //dc:date
+    pub creator: Option<String>, //dc:creator
+    pub subjects: Vec<String>,   //dc:subject
+    pub encoded: Option<String>, //content:encoded
+}
+
+/*読み込み中の要素がどこに属しているか*/
+enum Scope {
+    Other,
+    Channel,
+    Item(Item),
+}
+
+impl RDF {
+    pub fn new(s: &str) -> Result<Self, Error> {
+        Self::with_limit(s, None)
+    }
+
+    /*itemをmax_items件読んだところで読むのをやめる*/
+    pub fn with_limit(s: &str, max_items: Option<usize>) -> Result<Self, Error> {
+        let reader = ParserConfig::new()
+            .trim_whitespace(true)
+            .cdata_to_characters(true)
+            .coalesce_characters(true)
+            .ignore_comments(true)
+            .create_reader(s.as_bytes());
+
+        let mut rdf = RDF::default();
+        let mut scope = Scope::Other;
+        let mut depth = 0; //<rdf:RDF>を1とした深さ
+        let mut text = String::new();
+        for event in reader {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    depth += 1;
+                    text.clear();
+                    if depth == 2 && is(&name, RSS1_NS, "channel") {
+                        scope = Scope::Channel;
+                    } else if depth == 2 && is(&name, RSS1_NS, "item") {
+                        let about = attributes
+                            .into_iter()
+                            .find(|attr| is(&attr.name, RDF_NS, "about"))
+                            .map(|attr| attr.value);
+                        scope = Scope::Item(Item {
+                            about,
+                            ..Item::default()
+                        });
+                    }
+                }
+                XmlEvent::Characters(s) => text.push_str(&s),
+                XmlEvent::EndElement { name } => {
+                    let value = mem::take(&mut text);
+                    if depth == 2 {
+                        if let Scope::Item(item) = mem::replace(&mut scope, Scope::Other) {
+                            rdf.items.push(item);
+                            if matches!(max_items, Some(max_items) if rdf.items.len() >= max_items)
+                            {
+                                break;
+                            }
+                        }
+                    } else if depth == 3 {
+                        match &mut scope {
+                            Scope::Channel => set_channel_field(&mut rdf.channel, &name, value),
+                            Scope::Item(item) => set_item_field(item, &name, value),
+                            Scope::Other => (),
+                        }
+                    }
+                    depth -= 1;
+                }
+                _ => (),
+            }
+        }
+        Ok(rdf)
+    }
+}
+
+fn is(name: &OwnedName, namespace: &str, local_name: &str) -> bool {
+    name.namespace.as_deref() == Some(namespace) && name.local_name == local_name
+}
+
+fn set_channel_field(channel: &mut Channel, name: &OwnedName, value: String) {
+    match (name.namespace.as_deref(), name.local_name.as_str()) {
+        (Some(RSS1_NS), "title") => channel.title = value,
+        (Some(RSS1_NS), "link") => channel.link = Some(value),
+        (Some(RSS1_NS), "description") => channel.description = Some(value),
+        (Some(DC_NS), "date") => channel.date = Some(value),
+        _ => (),
+    }
+}
+
+fn set_item_field(item: &mut Item, name: &OwnedName, value: String) {
+    match (name.namespace.as_deref(), name.local_name.as_str()) {
+        (Some(RSS1_NS), "title") => item.title = value,
+        (Some(RSS1_NS), "link") => item.link = Some(value),
+        (Some(RSS1_NS), "description") => item.description = Some(value),
+        (Some(DC_NS), "date") => item.date = Some(value),
+        (Some(DC_NS), "creator") => item.creator = Some(value),
+        (Some(DC_NS), "subject") => item.subjects.push(value),
+        (Some(CONTENT_NS), "encoded") => item.encoded = Some(value),
+        _ => (),
+    }
+}
+
+impl From<RDF> for Feed {
+    fn from(rdf: RDF) -> Self {
+        Feed {
+            title: rdf.channel.title,
+            link: rdf.channel.link,
+            description: rdf.channel.description,
+            items: rdf
+                .items
+                .into_iter()
+                .map(|item| feed::Item {
+                    title: item.title,
+                    link: item.link.or(item.about.clone()),
+                    guid: item.about,
+                    pub_date: item.date.as_deref().map(PubDate::parse),
+                    description: item.description.as_deref().map(html::to_text),
+                    content: item.encoded.as_deref().map(html::to_text),
+                    author: item.creator,
+                    categories: item.subjects,
+                    image: None,
+                })
+                .collect(),
+            warnings: Vec::new(),
+            schedule: Schedule::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn make_rdf_test() {
+        //はてなブックマークのホットエントリーと同じ構造
+        let s = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <rdf:RDF
+              xmlns="http://purl.org/rss/1.0/"
+              xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"
+              xmlns:content="http://purl.org/rss/1.0/modules/content/"
+              xmlns:taxo="http://purl.org/rss/1.0/modules/taxonomy/"
+              xmlns:opensearch="http://a9.com/-/spec/opensearchrss/1.0/"
+              xmlns:dc="http://purl.org/dc/elements/1.1/"
+              xmlns:hatena="http://www.hatena.ne.jp/info/xmlns#"
+              xmlns:media="http://search.yahoo.com/mrss">
+              <channel rdf:about="https://b.hatena.ne.jp/hotentry/it">
+                <title>はてなブックマーク - 人気エントリー - テクノロジー</title>
+                <link>https://b.hatena.ne.jp/hotentry/it</link>
+                <description>最近の人気エントリー - テクノロジー</description>
+                <items>
+                  <rdf:Seq>
+                    <rdf:li rdf:resource="https://example.com/entry/1" />
+                    <rdf:li rdf:resource="https://example.com/entry/2" />
+                  </rdf:Seq>
+                </items>
+              </channel>
+              <item rdf:about="https://example.com/entry/1">
+                <title>Rustで組込み開発を始める</title>
+                <link>https://example.com/entry/1</link>
+                <description>組込み向けのRustの始め方をまとめました</description>
+                <content:encoded><![CDATA[<blockquote cite="https://example.com/entry/1"><p>組込み向けの<b>Rust</b></p></blockquote>]]></content:encoded>
+                <dc:date>2021-08-12T11:37:05+09:00</dc:date>
+                <dc:subject>テクノロジー</dc:subject>
+                <hatena:bookmarkcount>123</hatena:bookmarkcount>
+              </item>
+              <item rdf:about="https://example.com/entry/2">
+                <title>二件目のエントリー</title>
+                <dc:date>2021-08-12T10:00:00+09:00</dc:date>
+                <dc:creator>sabinote</dc:creator>
+              </item>
+            </rdf:RDF>
+        "#;
+        let rdf = RDF::new(s).unwrap();
+        assert_eq!(rdf.channel.date, None);
+        assert_eq!(
+            rdf.items.get(1).unwrap().creator.as_ref().unwrap(),
+            "sabinote"
+        );
+
+        let feed = Feed::from(rdf);
+        assert_eq!(
+            feed.title,
+            "はてなブックマーク - 人気エントリー - テクノロジー"
+        );
+        assert_eq!(feed.link.unwrap(), "https://b.hatena.ne.jp/hotentry/it");
+        assert_eq!(feed.items.len(), 2);
+
+        let item = feed.items.first().unwrap();
+        assert_eq!(item.title, "Rustで組込み開発を始める");
+        assert_eq!(item.link.as_ref().unwrap(), "https://example.com/entry/1");
+        assert_eq!(
+            item.pub_date.as_ref().unwrap().raw,
+            "2021-08-12T11:37:05+09:00"
+        );
+        assert_eq!(
+            item.description.as_ref().unwrap(),
+            "組込み向けのRustの始め方をまとめました"
+        );
+        assert_eq!(item.content.as_ref().unwrap(), "組込み向けのRust");
+
+        //linkがなければrdf:aboutを使う
+        let item = feed.items.get(1).unwrap();
+        assert_eq!(item.link.as_ref().unwrap(), "https://example.com/entry/2");
+        assert_eq!(item.description, None);
+    }
+
+    #[test]
+    fn namespace_test() {
+        //新聞社サイトのようにimageやtextinputを含み、接頭辞が一般的でないもの
+        let s = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <r:RDF xmlns:r="http://www.w3.org/1999/02/22-rdf-syntax-ns#"
+                   xmlns:rss="http://purl.org/rss/1.0/"
+                   xmlns:d="http://purl.org/dc/elements/1.1/"
+                   xmlns:other="http://example.com/other/">
+              <rss:channel r:about="https://example.jp/news/">
+                <rss:title>ニュース速報</rss:title>
+                <rss:link>https://example.jp/news/</rss:link>
+                <rss:description>最新のニュース</rss:description>
+                <d:date>2021-08-12T12:00:00+09:00</d:date>
+                <rss:image r:resource="https://example.jp/logo.png" />
+              </rss:channel>
+              <rss:image r:about="https://example.jp/logo.png">
+                <rss:title>ロゴ</rss:title>
+                <rss:url>https://example.jp/logo.png</rss:url>
+              </rss:image>
+              <rss:item r:about="https://example.jp/news/1">
+                <rss:title>台風が接近</rss:title>
+                <rss:link>https://example.jp/news/1</rss:link>
+                <other:date>1999-01-01</other:date>
+                <d:date>2021-08-12T11:00:00+09:00</d:date>
+              </rss:item>
+              <rss:textinput r:about="https://example.jp/search">
+                <rss:title>検索</rss:title>
+              </rss:textinput>
+            </r:RDF>
+        "#;
+        let rdf = RDF::new(s).unwrap();
+        assert_eq!(rdf.channel.title, "ニュース速報");
+        assert_eq!(
+            rdf.channel.date.as_ref().unwrap(),
+            "2021-08-12T12:00:00+09:00"
+        );
+        assert_eq!(rdf.items.len(), 1);
+        let item = rdf.items.first().unwrap();
+        assert_eq!(item.title, "台風が接近");
+        assert_eq!(item.date.as_ref().unwrap(), "2021-08-12T11:00:00+09:00");
+
+        assert!(
+            RDF::new("<r:RDF xmlns:r=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">").is_err()
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const MAX_IDS: usize = 10000; //覚えておく既読の記事の数 超えたら古いものから忘れる
+
+/*
+ * 既読の記事の記録
+ * ファイルには記事を見分ける値(feed::Item::id)を既読にした順に1行ずつ保存する
+ */
+#[derive(Default)]
+pub struct ReadLog {
+    path: Option<PathBuf>, //保存先 Noneならメモリ上だけで覚えておく
+    ids: VecDeque<String>,
+    set: HashSet<String>,
+}
+
+impl ReadLog {
+    /*pathから読み出す ファイルがなければ何も読んでいない状態から始める*/
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let mut log = Self::default();
+        match fs::read_to_string(&path) {
+            Ok(s) => {
+                for id in s.lines().filter(|id| !id.is_empty()) {
+                    log.mark_read(id);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+        log.path = Some(path);
+        Ok(log)
+    }
+
+    pub fn is_read(&self, id: &str) -> bool {
+        self.set.contains(id)
+    }
+
+    /*既読にする 既に既読ならfalseを返す*/
+    pub fn mark_read(&mut self, id: &str) -> bool {
+        if !self.set.insert(id.to_string()) {
+            return false;
+        }
+        self.ids.push_back(id.to_string());
+        if self.ids.len() > MAX_IDS {
+            if let Some(oldest) = self.ids.pop_front() {
+                self.set.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    /*未読に戻す 既読でなければfalseを返す*/
+    pub fn mark_unread(&mut self, id: &str) -> bool {
+        if !self.set.remove(id) {
+            return false;
+        }
+        self.ids.retain(|read| read != id);
+        true
+    }
+
+    /*一時ファイルに書いてから置き換える*/
+    pub fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut s = String::new();
+        for id in &self.ids {
+            s.push_str(id);
+            s.push('\n');
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, s)?;
+        fs::rename(tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn read_log_test() {
+        let path = std::env::temp_dir().join(format!("rss-reader-read-{}.txt", std::process::id()));
+        let mut log = ReadLog::open(&path).unwrap();
+        assert!(!log.is_read("a"));
+        assert!(log.mark_read("a"));
+        assert!(!log.mark_read("a"));
+        assert!(log.mark_read("b"));
+        assert!(log.mark_unread("a"));
+        assert!(!log.mark_unread("a"));
+        log.save().unwrap();
+
+        let log = ReadLog::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(!log.is_read("a"));
+        assert!(log.is_read("b"));
+
+        //上限を超えたら古いものから忘れる
+        let mut log = ReadLog::default();
+        for i in 0..=MAX_IDS {
+            log.mark_read(&i.to_string());
+        }
+        assert!(!log.is_read("0"));
+        assert!(log.is_read("1"));
+        assert!(log.is_read(&MAX_IDS.to_string()));
+    }
+}
@@ -0,0 +1,399 @@
+use crate::feed::{self, Feed};
+use crate::fetch::{self, ErrorKind, Fetched, Validators};
+use crate::keymap::Gesture;
+use crate::remote::Command;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/*
+ * 記録ファイルは1行に1イベントで、起動からの経過ミリ秒に続けてイベントの内容を並べる
+ *   ボタン入力      F1,F2,F3の押下状態 例: "1532 100" は起動1.532秒後にF1のみが押されたことを表す
+ *                   長押しの場合は末尾に"long"を付ける(例: "2010 010 long")
+ *   リモート操作    "remote"とコマンド 例: "2500 remote goto category 3"
+ *   フィードの取得  "fetched"、"prefetched"、"refreshed"のいずれかとカテゴリの番号、結果のJSON
+ *                   fetchedは直前の入力の処理の中で取得したもの、
+ *                   prefetchedとrefreshedは起動時の先読みと定期的な取得で裏で取得したもの
+ * 空行と#から始まる行は無視する
+ */
+
+/*記録ファイルの1行が表すイベント*/
+pub enum Record {
+    Gesture(Gesture),
+    Remote(Command),
+    Fetched(usize, Outcome),
+    Prefetched(usize, Outcome),
+    Refreshed(usize, Outcome),
+}
+
+/*フィードの取得の結果を記録ファイルに書ける形にしたもの*/
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Modified(Box<Feed>, Validators),
+    NotModified,
+    Timeout,
+    Status(u16),
+    TooLarge(u64),
+    Parse {
+        position: Option<(u64, u64)>,
+        reason: String,
+    },
+    UnknownFormat(String),
+    Failed(String), //それ以外の通信の失敗の原因
+}
+
+impl Outcome {
+    pub fn new(result: &Result<Fetched, fetch::Error>) -> Self {
+        match result {
+            Ok(Fetched::Modified(feed, validators)) => {
+                Outcome::Modified(feed.clone(), validators.clone())
+            }
+            Ok(Fetched::NotModified) => Outcome::NotModified,
+            Err(e) => match &e.kind {
+                ErrorKind::Timeout => Outcome::Timeout,
+                ErrorKind::Status(status) => Outcome::Status(status.as_u16()),
+                ErrorKind::TooLarge(max) => Outcome::TooLarge(*max),
+                ErrorKind::Parse(feed::Error::Parse { position, reason }) => Outcome::Parse {
+                    position: *position,
+                    reason: reason.clone(),
+                },
+                ErrorKind::Parse(feed::Error::UnknownFormat(name)) => {
+                    Outcome::UnknownFormat(name.clone())
+                }
+                kind => Outcome::Failed(kind.to_string()),
+            },
+        }
+    }
+
+    /*urlから取得した結果に戻す*/
+    pub fn into_result(self, url: &str) -> Result<Fetched, fetch::Error> {
+        let kind = match self {
+            Outcome::Modified(feed, validators) => return Ok(Fetched::Modified(feed, validators)),
+            Outcome::NotModified => return Ok(Fetched::NotModified),
+            Outcome::Timeout => ErrorKind::Timeout,
+            Outcome::Status(status) => match StatusCode::from_u16(status) {
+                Ok(status) => ErrorKind::Status(status),
+                Err(_) => ErrorKind::Recorded(format!("HTTP status {}", status)),
+            },
+            Outcome::TooLarge(max) => ErrorKind::TooLarge(max),
+            Outcome::Parse { position, reason } => {
+                ErrorKind::Parse(feed::Error::Parse { position, reason })
+            }
+            Outcome::UnknownFormat(name) => ErrorKind::Parse(feed::Error::UnknownFormat(name)),
+            Outcome::Failed(reason) => ErrorKind::Recorded(reason),
+        };
+        Err(fetch::Error {
+            url: url.to_string(),
+            kind,
+        })
+    }
+}
+
+/*入力と取得の結果をタイムスタンプ付きでファイルに記録する構造体*/
+pub struct Recorder {
+    file: File,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            file: File::create(path)?,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, record: &Record) -> io::Result<()> {
+        let elapsed = self.start.elapsed().as_millis();
+        //途中で異常終了しても記録が残るように1行ずつ書き出す
+        writeln!(self.file, "{} {}", elapsed, format_record(record)?)?;
+        self.file.flush()
+    }
+}
+
+/*記録したイベントを同じ時間間隔で再生する構造体*/
+pub struct Replayer {
+    events: VecDeque<(Duration, Record)>,
+    start: Instant,
+}
+
+impl Replayer {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(&fs::read_to_string(path)?)
+    }
+
+    pub fn new(s: &str) -> io::Result<Self> {
+        let records = s
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(i, line)| {
+                parse_line(line).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid record at line {}: {:?}", i + 1, line),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        //入力の処理の中で取得した結果はその入力の後に記録されるので、入力より先に渡す
+        let mut events = VecDeque::with_capacity(records.len());
+        let mut input = None;
+        for (at, record) in records {
+            match (&record, input) {
+                (Record::Fetched(..), Some(j)) => {
+                    let (at, _) = events[j];
+                    events.insert(j, (at, record));
+                    input = Some(j + 1);
+                }
+                (Record::Gesture(_), _) | (Record::Remote(_), _) => {
+                    input = Some(events.len());
+                    events.push_back((at, record));
+                }
+                _ => events.push_back((at, record)),
+            }
+        }
+        Ok(Self {
+            events,
+            start: Instant::now(),
+        })
+    }
+
+    /*次のイベントの時刻まで待ってから返す 記録が尽きたらNone*/
+    pub async fn event(&mut self) -> Option<Record> {
+        let (at, record) = self.events.pop_front()?;
+        tokio::time::delay_until(self.start + at).await;
+        Some(record)
+    }
+}
+
+fn format_record(record: &Record) -> io::Result<String> {
+    let (kind, i, outcome) = match record {
+        Record::Gesture(gesture) => {
+            let pressed = format_pressed(gesture.buttons);
+            return Ok(match gesture.long {
+                true => format!("{} long", pressed),
+                false => pressed,
+            });
+        }
+        Record::Remote(command) => return Ok(format!("remote {}", command)),
+        Record::Fetched(i, outcome) => ("fetched", i, outcome),
+        Record::Prefetched(i, outcome) => ("prefetched", i, outcome),
+        Record::Refreshed(i, outcome) => ("refreshed", i, outcome),
+    };
+    Ok(format!(
+        "{} {} {}",
+        kind,
+        i,
+        serde_json::to_string(outcome)?
+    ))
+}
+
+fn format_pressed(pressed: [bool; 3]) -> String {
+    pressed.iter().map(|&p| if p { '1' } else { '0' }).collect()
+}
+
+/*先頭の空白で区切られた語と、その後の空白を除いた残りに分ける*/
+fn split_word(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    match s.find(char::is_whitespace) {
+        Some(n) => Some((&s[..n], s[n..].trim())),
+        None if s.is_empty() => None,
+        None => Some((s, "")),
+    }
+}
+
+fn parse_line(line: &str) -> Option<(Duration, Record)> {
+    let (millis, rest) = split_word(line)?;
+    let millis = millis.parse::<u64>().ok()?;
+    let (kind, rest) = split_word(rest)?;
+    let record = match kind {
+        "remote" => Record::Remote(Command::parse(rest).ok()?),
+        "fetched" | "prefetched" | "refreshed" => {
+            let (i, json) = split_word(rest)?;
+            let i = i.parse().ok()?;
+            let outcome = serde_json::from_str(json).ok()?;
+            match kind {
+                "fetched" => Record::Fetched(i, outcome),
+                "prefetched" => Record::Prefetched(i, outcome),
+                _ => Record::Refreshed(i, outcome),
+            }
+        }
+        bits => Record::Gesture(parse_gesture(bits, rest)?),
+    };
+    Some((Duration::from_millis(millis), record))
+}
+
+fn parse_gesture(bits: &str, rest: &str) -> Option<Gesture> {
+    let long = match rest {
+        "long" => true,
+        "" => false,
+        _ => return None,
+    };
+    let bits = bits.as_bytes();
+    if bits.len() != 3 {
+        return None;
+    }
+    let mut pressed = [false; 3];
+    for (p, b) in pressed.iter_mut().zip(bits) {
+        *p = match b {
+            b'0' => false,
+            b'1' => true,
+            _ => return None,
+        };
+    }
+    Some(Gesture {
+        buttons: pressed,
+        long,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::Action;
+
+    /*ボタン入力の行を読み込む*/
+    fn parse_gesture_line(line: &str) -> Option<(Duration, Gesture)> {
+        match parse_line(line)? {
+            (at, Record::Gesture(gesture)) => Some((at, gesture)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parse_line_test() {
+        assert_eq!(
+            parse_gesture_line("1532 100"),
+            Some((
+                Duration::from_millis(1532),
+                Gesture::press([true, false, false])
+            ))
+        );
+        assert_eq!(
+            parse_gesture_line("0 101"),
+            Some((
+                Duration::from_millis(0),
+                Gesture::press([true, false, true])
+            ))
+        );
+        assert_eq!(
+            parse_gesture_line("2010 010 long"),
+            Some((
+                Duration::from_millis(2010),
+                Gesture::long_press([false, true, false])
+            ))
+        );
+        assert!(parse_line("10 10").is_none());
+        assert!(parse_line("10 102").is_none());
+        assert!(parse_line("x 100").is_none());
+        assert!(parse_line("10 100 1").is_none());
+        assert!(parse_line("10 100 long 1").is_none());
+        assert_eq!(format_pressed([false, true, true]), "011");
+
+        assert!(matches!(
+            parse_line("2500 remote goto category 3"),
+            Some((_, Record::Remote(Command::Goto(3))))
+        ));
+        assert!(parse_line("2500 remote jump").is_none());
+        assert!(matches!(
+            parse_line(r#"300 prefetched 2 "not_modified""#),
+            Some((_, Record::Prefetched(2, Outcome::NotModified)))
+        ));
+        assert!(parse_line("300 refreshed 2 {broken").is_none());
+        assert!(parse_line(r#"300 fetched x "not_modified""#).is_none());
+    }
+
+    #[test]
+    fn format_record_test() {
+        let records = [
+            Record::Gesture(Gesture::long_press([false, true, false])),
+            Record::Remote(Command::Action(Action::ReadAll)),
+            Record::Refreshed(1, Outcome::Status(503)),
+        ];
+        let lines = records
+            .iter()
+            .map(|record| format_record(record).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "010 long",
+                "remote read_all",
+                r#"refreshed 1 {"status":503}"#
+            ]
+        );
+        //書き出したものは同じ結果として読み込める
+        let line = format!("0 {}", lines[2]);
+        let result = match parse_line(&line) {
+            Some((_, Record::Refreshed(1, outcome))) => outcome.into_result("http://example.com/"),
+            _ => panic!("{} is not parsed", line),
+        };
+        let e = result.err().unwrap();
+        assert_eq!(e.url, "http://example.com/");
+        assert!(matches!(
+            e.kind,
+            ErrorKind::Status(StatusCode::SERVICE_UNAVAILABLE)
+        ));
+    }
+
+    #[test]
+    fn outcome_test() {
+        let feed = Feed::new(
+            r#"<?xml version="1.0"?><rss><channel><title>t</title><item><title>a</title></item></channel></rss>"#,
+        )
+        .unwrap();
+        let result = Ok(Fetched::Modified(Box::new(feed), Validators::default()));
+        let json = serde_json::to_string(&Outcome::new(&result)).unwrap();
+        let outcome = serde_json::from_str::<Outcome>(&json).unwrap();
+        match outcome.into_result("http://example.com/") {
+            Ok(Fetched::Modified(feed, _)) => assert_eq!(feed.items[0].title, "a"),
+            _ => panic!("the feed is not restored"),
+        }
+        //通信の失敗は原因の文字列として再現する
+        let result = Err(fetch::Error {
+            url: "http://example.com/".to_string(),
+            kind: ErrorKind::Recorded("connection refused".to_string()),
+        });
+        match Outcome::new(&result).into_result("http://example.com/") {
+            Err(fetch::Error {
+                kind: ErrorKind::Recorded(reason),
+                ..
+            }) => assert_eq!(reason, "connection refused"),
+            _ => panic!("the failure is not restored"),
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_test() {
+        let mut replayer = Replayer::new(
+            "# comment\n0 100\n\n5 001 long\n6 fetched 0 \"not_modified\"\n7 remote status\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            replayer.event().await,
+            Some(Record::Gesture(gesture)) if gesture == Gesture::press([true, false, false])
+        ));
+        //入力の処理の中で取得した結果はその入力より先に渡す
+        assert!(matches!(
+            replayer.event().await,
+            Some(Record::Fetched(0, Outcome::NotModified))
+        ));
+        assert!(matches!(
+            replayer.event().await,
+            Some(Record::Gesture(gesture)) if gesture == Gesture::long_press([false, false, true])
+        ));
+        assert!(matches!(
+            replayer.event().await,
+            Some(Record::Remote(Command::Status))
+        ));
+        assert!(replayer.event().await.is_none());
+        assert!(Replayer::new("0 100\nbroken\n").is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::event::Event;
+use crate::keymap::Action;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::{mpsc, oneshot};
+
+/*
+ * 1行に1コマンドを送ると、処理後に1行の応答が返る
+ *   up / down / select / back   ボタン操作と同じ動作
+ *   refresh / home              キー割り当てで使える動作と同じ
+ *   goto category <n>           n番目(0始まり)のカテゴリのタイトル一覧を開く
+ *   status                      何もせず現在の画面を返す
+ * 応答は成功なら"ok <画面>"(例: "ok title 3 5")、失敗なら"err <理由>"
+ */
+
+/*リモートから送られてくるコマンド*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Action(Action),
+    Goto(usize),
+    Status,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["status"] => Ok(Command::Status),
+            ["goto", "category", n] => n
+                .parse()
+                .map(Command::Goto)
+                .map_err(|_| format!("invalid category: {}", n)),
+            [action] => Action::parse(action)
+                .map(Command::Action)
+                .ok_or_else(|| format!("unknown command: {}", line.trim())),
+            _ => Err(format!("unknown command: {}", line.trim())),
+        }
+    }
+}
+
+/*parseで読み込める形で書き出す*/
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Action(action) => write!(f, "{}", action),
+            Command::Goto(i) => write!(f, "goto category {}", i),
+            Command::Status => write!(f, "status"),
+        }
+    }
+}
+
+/*UNIXドメインソケットでコマンドを待ち受ける*/
+pub async fn serve_unix<P: AsRef<Path>>(path: P, tx: mpsc::Sender<Event>) -> io::Result<()> {
+    remove_stale_socket(path.as_ref())?;
+    let mut listener = UnixListener::bind(path)?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle(stream, tx.clone()));
+    }
+}
+
+/*前回起動時のソケットが残っていれば消す 同じパスにソケット以外のファイルがあれば消さずにエラーにする*/
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{:?} exists and is not a socket", path),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/*TCPでコマンドを待ち受ける*/
+pub async fn serve_tcp(addr: &str, tx: mpsc::Sender<Event>) -> io::Result<()> {
+    let mut listener = TcpListener::bind(addr).await?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle(stream, tx.clone()));
+    }
+}
+
+/*ひとつの接続から届くコマンドをイベントとして流し、その応答を書き戻す*/
+async fn handle<S>(stream: S, mut tx: mpsc::Sender<Event>) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match Command::parse(&line) {
+            Ok(command) => {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                if tx.send(Event::Remote(command, reply_tx)).await.is_err() {
+                    break; //メインループが終了している
+                }
+                match reply_rx.await {
+                    Ok(reply) => reply,
+                    Err(_) => break,
+                }
+            }
+            Err(e) => Err(e),
+        };
+        let reply = match reply {
+            Ok(screen) => format!("ok {}\n", screen),
+            Err(e) => format!("err {}\n", e),
+        };
+        writer.write_all(reply.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UnixStream;
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(Command::parse("up"), Ok(Command::Action(Action::Up)));
+        assert_eq!(
+            Command::parse(" down \r"),
+            Ok(Command::Action(Action::Down))
+        );
+        assert_eq!(
+            Command::parse("select"),
+            Ok(Command::Action(Action::Select))
+        );
+        assert_eq!(Command::parse("back"), Ok(Command::Action(Action::Back)));
+        assert_eq!(Command::parse("home"), Ok(Command::Action(Action::Home)));
+        assert_eq!(Command::parse("status"), Ok(Command::Status));
+        assert_eq!(Command::parse("goto category 3"), Ok(Command::Goto(3)));
+        assert!(Command::parse("goto category -1").is_err());
+        assert!(Command::parse("goto 3").is_err());
+        assert!(Command::parse("jump").is_err());
+        for line in &["read_all", "goto category 3", "status"] {
+            assert_eq!(Command::parse(line).unwrap().to_string(), *line);
+        }
+    }
+
+    #[test]
+    fn remove_stale_socket_test() {
+        let path = std::env::temp_dir().join(format!("rss-reader-{}.sock", std::process::id()));
+        assert!(remove_stale_socket(&path).is_ok());
+        //ソケット以外のファイルは消さない
+        fs::write(&path, "data").unwrap();
+        assert!(remove_stale_socket(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "data");
+        fs::remove_file(&path).unwrap();
+        //残っていたソケットは消す
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        drop(listener);
+        remove_stale_socket(&path).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn handle_test() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let (tx, mut rx) = mpsc::channel(1);
+        tokio::spawn(handle(server, tx));
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                if let Event::Remote(command, reply) = event {
+                    let _ = reply.send(match command {
+                        Command::Goto(i) => Ok(format!("title {} 0", i)),
+                        _ => Err("unsupported".to_string()),
+                    });
+                }
+            }
+        });
+
+        let (reader, mut writer) = tokio::io::split(client);
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"goto category 3\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok title 3 0");
+        writer.write_all(b"up\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "err unsupported");
+        writer.write_all(b"fly\n").await.unwrap();
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            "err unknown command: fly"
+        );
+    }
+}
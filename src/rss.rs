@@ -1,44 +1,386 @@
-use serde::Deserialize;
-use serde_xml_rs::Error;
+use crate::date::PubDate;
+use crate::feed::{self, Feed, Schedule, Warning};
+use crate::html;
+use std::mem;
+use xml::attribute::OwnedAttribute;
+use xml::name::OwnedName;
+use xml::reader::{Error, ParserConfig, XmlEvent};
 
-#[derive(Deserialize)]
-#[serde(rename = "rss")]
+const CONTENT_NS: &str = "http://purl.org/rss/1.0/modules/content/";
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+const MEDIA_NS: &str = "http://search.yahoo.com/mrss"; //末尾の"/"の有無はフィードによる
+
+/*
+ * RSS 2.0のフィード
+ * 仕様上省略できる要素はすべてOptionで受け、知らない要素は読み飛ばす
+ * 巨大なフィードでもメモリを使いすぎないよう、文書を先頭から順に読みながら組み立てる
+ */
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Default)]
 pub struct RSS {
     pub channel: Channel,
 }
 
-impl RSS {
-    pub fn new(s: &str) -> Result<Self, Error> {
-        serde_xml_rs::from_str(s)
-    }
-}
-
-#[derive(Deserialize)]
+#[derive(Default)]
 pub struct Channel {
-    pub language: String,
-    pub copyright: String,
-    #[serde(rename = "pubDate")]
-    pub pub_date: String,
+    //title、link、descriptionは必須だが、欠けていても読み込みは続ける
     pub title: String,
     pub link: String,
     pub description: String,
-    #[serde(rename = "item")]
+    pub language: Option<String>,
+    pub copyright: Option<String>,
+    pub pub_date: Option<String>,
+    pub last_build_date: Option<String>,
+    pub generator: Option<String>,
+    pub ttl: Option<String>,
+    pub skip_hours: Vec<String>, //skipHoursのhour
+    pub skip_days: Vec<String>,  //skipDaysのday
     pub items: Vec<Item>,
 }
 
-#[derive(Deserialize)]
+#[derive(Default)]
 pub struct Item {
-    pub title: String,
-    pub link: String,
-    #[serde(rename = "pubDate")]
-    pub pub_date: String,
+    //titleとdescriptionはどちらか一方があれば良い
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub pub_date: Option<String>,
     pub description: Option<String>,
+    pub author: Option<String>,
+    pub categories: Vec<String>,
     pub comments: Option<String>,
+    pub guid: Option<String>,
+    pub encoded: Option<String>, //content:encoded
+    pub creator: Option<String>, //dc:creator
+    pub image: Option<String>,   //画像のurl(Yahoo!ニュースなど)
+    pub enclosures: Vec<Enclosure>,
+    pub thumbnails: Vec<Media>,        //media:thumbnail
+    pub media_contents: Vec<Media>,    //media:content
+    pub media_groups: Vec<MediaGroup>, //media:group
+}
+
+/*添付ファイル*/
+pub struct Enclosure {
+    pub url: String,
+    pub mime: Option<String>,
+    pub length: Option<String>,
+}
+
+/*Media RSSのmedia:thumbnailとmedia:content*/
+pub struct Media {
+    pub url: Option<String>,
+    pub medium: Option<String>,
+    pub mime: Option<String>,
+}
+
+#[derive(Default)]
+pub struct MediaGroup {
+    pub thumbnails: Vec<Media>,
+    pub media_contents: Vec<Media>,
+}
+
+impl RSS {
+    pub fn new(s: &str) -> Result<Self, Error> {
+        Self::with_limit(s, None)
+    }
+
+    /*
+     * itemをmax_items件読んだところで読むのをやめる
+     * itemは新しい順に並んでいるものとし、それより後ろにあるチャンネルの要素は読まない
+     */
+    pub fn with_limit(s: &str, max_items: Option<usize>) -> Result<Self, Error> {
+        let reader = ParserConfig::new()
+            .trim_whitespace(true)
+            .cdata_to_characters(true)
+            .coalesce_characters(true)
+            .ignore_comments(true)
+            .create_reader(s.as_bytes());
+
+        let mut rss = RSS::default();
+        let mut stack = Vec::new(); //開いている要素(<rss>が先頭)
+        let mut item = None; //読み込み中のitem
+        let mut text = String::new();
+        for event in reader {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    text.clear();
+                    stack.push(name);
+                    if !in_channel(&stack) {
+                        continue;
+                    }
+                    let name = &stack[stack.len() - 1];
+                    match (stack.len(), &mut item) {
+                        (3, None) if is_plain(name, "item") => item = Some(Item::default()),
+                        (4, Some(item)) => start_item_element(item, name, attributes),
+                        (5, Some(item)) if is_media(&stack[3], "group") => {
+                            if let Some(group) = item.media_groups.last_mut() {
+                                start_group_element(group, name, attributes);
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                XmlEvent::Characters(s) => text.push_str(&s),
+                XmlEvent::EndElement { .. } => {
+                    let value = mem::take(&mut text);
+                    if in_channel(&stack) {
+                        let name = &stack[stack.len() - 1];
+                        match stack.len() {
+                            3 => match item.take() {
+                                Some(item) => rss.channel.items.push(item),
+                                None => set_channel_field(&mut rss.channel, name, value),
+                            },
+                            4 => match &mut item {
+                                Some(item) => set_item_field(item, name, value),
+                                None => {
+                                    set_schedule_field(&mut rss.channel, &stack[2], name, value)
+                                }
+                            },
+                            _ => (),
+                        }
+                    }
+                    stack.pop();
+                    if matches!(max_items, Some(max_items) if rss.channel.items.len() >= max_items)
+                    {
+                        break;
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(rss)
+    }
+}
+
+/*<rss><channel>の中を読んでいるか*/
+fn in_channel(stack: &[OwnedName]) -> bool {
+    stack.len() >= 2 && is_plain(&stack[0], "rss") && is_plain(&stack[1], "channel")
+}
+
+/*名前空間のないRSS 2.0の要素か*/
+fn is_plain(name: &OwnedName, local_name: &str) -> bool {
+    name.namespace.is_none() && name.local_name == local_name
+}
+
+fn is_media(name: &OwnedName, local_name: &str) -> bool {
+    matches!(&name.namespace, Some(ns) if ns.trim_end_matches('/') == MEDIA_NS)
+        && name.local_name == local_name
+}
+
+fn attribute(attributes: &[OwnedAttribute], local_name: &str) -> Option<String> {
+    attributes
+        .iter()
+        .find(|attr| attr.name.namespace.is_none() && attr.name.local_name == local_name)
+        .map(|attr| attr.value.clone())
+}
+
+fn media(attributes: &[OwnedAttribute]) -> Media {
+    Media {
+        url: attribute(attributes, "url"),
+        medium: attribute(attributes, "medium"),
+        mime: attribute(attributes, "type"),
+    }
+}
+
+/*属性だけを持つitemの子要素*/
+fn start_item_element(item: &mut Item, name: &OwnedName, attributes: Vec<OwnedAttribute>) {
+    if is_plain(name, "enclosure") {
+        if let Some(url) = attribute(&attributes, "url") {
+            item.enclosures.push(Enclosure {
+                url,
+                mime: attribute(&attributes, "type"),
+                length: attribute(&attributes, "length"),
+            });
+        }
+    } else if is_media(name, "thumbnail") {
+        item.thumbnails.push(media(&attributes));
+    } else if is_media(name, "content") {
+        item.media_contents.push(media(&attributes));
+    } else if is_media(name, "group") {
+        item.media_groups.push(MediaGroup::default());
+    }
+}
+
+fn start_group_element(group: &mut MediaGroup, name: &OwnedName, attributes: Vec<OwnedAttribute>) {
+    if is_media(name, "thumbnail") {
+        group.thumbnails.push(media(&attributes));
+    } else if is_media(name, "content") {
+        group.media_contents.push(media(&attributes));
+    }
+}
+
+fn set_channel_field(channel: &mut Channel, name: &OwnedName, value: String) {
+    if name.namespace.is_some() {
+        return;
+    }
+    match name.local_name.as_str() {
+        "title" => channel.title = value,
+        "link" => channel.link = value,
+        "description" => channel.description = value,
+        "language" => channel.language = Some(value),
+        "copyright" => channel.copyright = Some(value),
+        "pubDate" => channel.pub_date = Some(value),
+        "lastBuildDate" => channel.last_build_date = Some(value),
+        "generator" => channel.generator = Some(value),
+        "ttl" => channel.ttl = Some(value),
+        _ => (),
+    }
+}
+
+/*<skipHours><hour>と<skipDays><day>*/
+fn set_schedule_field(channel: &mut Channel, parent: &OwnedName, name: &OwnedName, value: String) {
+    if is_plain(parent, "skipHours") && is_plain(name, "hour") {
+        channel.skip_hours.push(value);
+    } else if is_plain(parent, "skipDays") && is_plain(name, "day") {
+        channel.skip_days.push(value);
+    }
+}
+
+fn set_item_field(item: &mut Item, name: &OwnedName, value: String) {
+    match (name.namespace.as_deref(), name.local_name.as_str()) {
+        (None, "title") => item.title = Some(value),
+        (None, "link") => item.link = Some(value),
+        (None, "pubDate") => item.pub_date = Some(value),
+        (None, "description") => item.description = Some(value),
+        (None, "author") => item.author = Some(value),
+        (None, "category") => item.categories.push(value),
+        (None, "comments") => item.comments = Some(value),
+        (None, "guid") => item.guid = Some(value),
+        (None, "image") => item.image = Some(value),
+        (Some(CONTENT_NS), "encoded") => item.encoded = Some(value),
+        (Some(DC_NS), "creator") => item.creator = Some(value),
+        _ => (),
+    }
+}
+
+impl Media {
+    fn is_image(&self) -> bool {
+        self.medium.as_deref() == Some("image") || is_image_type(self.mime.as_deref())
+    }
+}
+
+fn is_image_type(mime: Option<&str>) -> bool {
+    matches!(mime, Some(mime) if mime.starts_with("image/"))
+}
+
+impl Item {
+    /*表示に使う画像のurl 小さいサムネイルを優先する*/
+    pub fn image_url(&self) -> Option<&str> {
+        let groups = || self.media_groups.iter();
+        let thumbnails = self
+            .thumbnails
+            .iter()
+            .chain(groups().flat_map(|group| group.thumbnails.iter()));
+        let contents = self
+            .media_contents
+            .iter()
+            .chain(groups().flat_map(|group| group.media_contents.iter()))
+            .filter(|media| media.is_image());
+        thumbnails
+            .chain(contents)
+            .find_map(|media| media.url.as_deref())
+            .or_else(|| {
+                self.enclosures
+                    .iter()
+                    .find(|enclosure| is_image_type(enclosure.mime.as_deref()))
+                    .map(|enclosure| enclosure.url.as_str())
+            })
+            .or(self.image.as_deref())
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+    }
+}
+
+impl Channel {
+    /*ttl、skipHours、skipDaysを読む 解釈できない値は無視する*/
+    pub fn schedule(&self) -> Schedule {
+        let ttl = self.ttl.as_deref().and_then(|ttl| ttl.trim().parse().ok());
+        let skip_hours = self
+            .skip_hours
+            .iter()
+            .filter_map(|hour| hour.trim().parse().ok())
+            .filter(|hour| *hour < 24)
+            .collect();
+        let skip_days = self
+            .skip_days
+            .iter()
+            .filter_map(|day| day.trim().parse().ok())
+            .collect();
+        Schedule {
+            ttl,
+            skip_hours,
+            skip_days,
+        }
+    }
+
+    /*仕様に沿っていない箇所を集める*/
+    pub fn warnings(&self) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        for (name, value) in &[
+            ("title", &self.title),
+            ("link", &self.link),
+            ("description", &self.description),
+        ] {
+            if value.is_empty() {
+                warnings.push(Warning::channel(format!("missing <{}>", name)));
+            }
+        }
+        for (i, item) in self.items.iter().enumerate() {
+            if item.title.is_none() && item.description.is_none() {
+                warnings.push(Warning::item(i, "missing both <title> and <description>"));
+            }
+            if item.link.is_none() && item.guid.is_none() {
+                warnings.push(Warning::item(i, "missing both <link> and <guid>"));
+            }
+        }
+        warnings
+    }
+}
+
+impl From<RSS> for Feed {
+    fn from(rss: RSS) -> Self {
+        let channel = rss.channel;
+        let warnings = channel.warnings();
+        let schedule = channel.schedule();
+        Feed {
+            title: channel.title,
+            link: Some(channel.link).filter(|link| !link.is_empty()),
+            description: Some(channel.description).filter(|description| !description.is_empty()),
+            items: channel
+                .items
+                .into_iter()
+                .map(|item| {
+                    let image = item.image_url().map(str::to_string);
+                    let description = item.description.as_deref().map(html::to_text);
+                    //titleのないitemは説明をタイトルの代わりにする
+                    let title = match item.title {
+                        Some(title) => title,
+                        None => description.as_deref().unwrap_or("").replace('\n', " "),
+                    };
+                    feed::Item {
+                        title,
+                        link: item.link,
+                        guid: item.guid,
+                        pub_date: item.pub_date.as_deref().map(PubDate::parse),
+                        description,
+                        content: item.encoded.as_deref().map(html::to_text),
+                        author: item.creator.or(item.author),
+                        categories: item.categories,
+                        image,
+                    }
+                })
+                .collect(),
+            warnings,
+            schedule,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::Weekday;
     #[test]
     fn make_rss_test() {
         let s = r#"
@@ -60,22 +402,227 @@ mod tests {
                 </channel>
             </rss>
         "#;
-        let rss = RSS::new(&s).unwrap();
+        let rss = RSS::new(s).unwrap();
         let channel = &rss.channel;
-        assert_eq!(channel.language, "ja");
-        assert_eq!(channel.copyright, "sabinote");
-        assert_eq!(channel.pub_date, "2021-08-12T12:03:56.577Z");
+        assert_eq!(channel.language.as_ref().unwrap(), "ja");
+        assert_eq!(channel.copyright.as_ref().unwrap(), "sabinote");
+        assert_eq!(
+            channel.pub_date.as_ref().unwrap(),
+            "2021-08-12T12:03:56.577Z"
+        );
         assert_eq!(channel.title, "タイトルです");
         assert_eq!(channel.link, "リンクです");
         assert_eq!(channel.description, "説明です");
 
         let items = &channel.items;
         assert_eq!(items.len(), 1);
-        let item = items.get(0).unwrap();
-        assert_eq!(item.title, "アイテムのタイトルです");
-        assert_eq!(item.link, "アイテムへのリンクです");
-        assert_eq!(item.pub_date, "2021-08-12T11:37:05.000Z");
-        assert_eq!(item.description.unwrap(), "アイテムの説明です");
-        assert_eq!(item.comments.unwrap(), "アイテムへのコメントです");
+        let item = items.first().unwrap();
+        assert_eq!(item.title.as_ref().unwrap(), "アイテムのタイトルです");
+        assert_eq!(item.link.as_ref().unwrap(), "アイテムへのリンクです");
+        assert_eq!(item.pub_date.as_ref().unwrap(), "2021-08-12T11:37:05.000Z");
+        assert_eq!(item.description.as_ref().unwrap(), "アイテムの説明です");
+        assert_eq!(item.comments.as_ref().unwrap(), "アイテムへのコメントです");
+
+        let feed = Feed::from(rss);
+        let pub_date = feed.items[0].pub_date.as_ref().unwrap();
+        assert_eq!(pub_date.raw, "2021-08-12T11:37:05.000Z");
+        assert_eq!(
+            pub_date.time.unwrap().to_rfc3339(),
+            "2021-08-12T11:37:05+00:00"
+        );
+    }
+
+    #[test]
+    fn lenient_rss_test() {
+        let s = r#"
+            <rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+                <channel>
+                    <title>ブログ</title>
+                    <atom:link href="https://example.com/feed" rel="self"/>
+                    <item>
+                        <title>リンクのない記事</title>
+                        <guid isPermaLink="false">post-1</guid>
+                    </item>
+                    <generator>example</generator>
+                    <item>
+                        <description>タイトルのない記事</description>
+                        <link>https://example.com/2</link>
+                        <unknown><nested>無視される</nested></unknown>
+                    </item>
+                    <item>
+                        <category>空の記事</category>
+                    </item>
+                </channel>
+            </rss>
+        "#;
+        let rss = RSS::new(s).unwrap();
+        assert_eq!(rss.channel.items.len(), 3);
+        assert_eq!(rss.channel.items[0].guid.as_ref().unwrap(), "post-1");
+        assert_eq!(rss.channel.generator.as_ref().unwrap(), "example");
+
+        let feed = Feed::from(rss);
+        assert_eq!(feed.link, None);
+        assert_eq!(feed.items.len(), 3);
+        assert_eq!(feed.items[0].title, "リンクのない記事");
+        assert_eq!(feed.items[1].title, "タイトルのない記事");
+        assert_eq!(feed.items[2].title, "");
+        let warnings = feed
+            .warnings
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            [
+                "channel: missing <link>",
+                "channel: missing <description>",
+                "item 2: missing both <title> and <description>",
+                "item 2: missing both <link> and <guid>",
+            ]
+        );
+    }
+
+    #[test]
+    fn image_test() {
+        let s = r#"
+            <rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/">
+                <channel>
+                    <title>写真</title>
+                    <link>https://example.com/</link>
+                    <description>画像付きの記事</description>
+                    <image><url>https://example.com/logo.png</url></image>
+                    <item>
+                        <title>添付ファイル</title>
+                        <enclosure url="https://example.com/a.mp3" type="audio/mpeg" length="100"/>
+                        <enclosure url="https://example.com/a.jpg" type="image/jpeg" length="200"/>
+                    </item>
+                    <item>
+                        <title>サムネイル</title>
+                        <media:content url="https://example.com/b.mp4" medium="video"/>
+                        <media:content url="https://example.com/b.jpg" medium="image"/>
+                        <media:thumbnail url="https://example.com/b_s.jpg" width="120" height="90"/>
+                    </item>
+                    <item>
+                        <title>グループ</title>
+                        <media:group>
+                            <media:content url="https://example.com/c.webp" type="image/webp"/>
+                        </media:group>
+                    </item>
+                    <item>
+                        <title>Yahoo!ニュース</title>
+                        <image>https://example.com/d.png</image>
+                    </item>
+                    <item>
+                        <title>画像なし</title>
+                        <enclosure url="https://example.com/e.mp3" type="audio/mpeg" length="100"/>
+                    </item>
+                </channel>
+            </rss>
+        "#;
+        let feed = Feed::from(RSS::new(s).unwrap());
+        let images = feed
+            .items
+            .iter()
+            .map(|item| item.image.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            images,
+            [
+                Some("https://example.com/a.jpg"),
+                Some("https://example.com/b_s.jpg"),
+                Some("https://example.com/c.webp"),
+                Some("https://example.com/d.png"),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn extension_test() {
+        let s = r#"
+            <rss version="2.0"
+                 xmlns:content="http://purl.org/rss/1.0/modules/content/"
+                 xmlns:dc="http://purl.org/dc/elements/1.1/">
+                <channel>
+                    <title>ブログ</title>
+                    <link>https://example.com/</link>
+                    <description>技術ブログ</description>
+                    <item>
+                        <title>記事</title>
+                        <description>概要です</description>
+                        <content:encoded><![CDATA[<p>本文の<b>全文</b>です</p>]]></content:encoded>
+                        <dc:creator>sabinote</dc:creator>
+                        <category>Rust</category>
+                        <category domain="https://example.com/tags">組込み</category>
+                    </item>
+                    <item>
+                        <title>拡張なし</title>
+                        <author>editor@example.com (編集部)</author>
+                    </item>
+                </channel>
+            </rss>
+        "#;
+        let feed = Feed::from(RSS::new(s).unwrap());
+        let item = &feed.items[0];
+        assert_eq!(item.description.as_ref().unwrap(), "概要です");
+        assert_eq!(item.content.as_ref().unwrap(), "本文の全文です");
+        assert_eq!(item.author.as_ref().unwrap(), "sabinote");
+        assert_eq!(item.categories, ["Rust", "組込み"]);
+
+        let item = &feed.items[1];
+        assert_eq!(item.content, None);
+        assert_eq!(item.author.as_ref().unwrap(), "editor@example.com (編集部)");
+        assert!(item.categories.is_empty());
+    }
+
+    #[test]
+    fn schedule_test() {
+        let s = r#"
+            <rss version="2.0">
+                <channel>
+                    <title>ニュース</title>
+                    <link>https://example.com/</link>
+                    <description>毎時更新</description>
+                    <ttl> 60 </ttl>
+                    <skipHours><hour>0</hour><hour>1</hour><hour>24</hour></skipHours>
+                    <skipDays><day>Saturday</day><day>Sunday</day><day>Holiday</day></skipDays>
+                </channel>
+            </rss>
+        "#;
+        let feed = Feed::from(RSS::new(s).unwrap());
+        assert_eq!(
+            feed.schedule,
+            Schedule {
+                ttl: Some(60),
+                skip_hours: vec![0, 1],
+                skip_days: vec![Weekday::Sat, Weekday::Sun],
+            }
+        );
+        assert!(feed.items.is_empty());
+    }
+
+    #[test]
+    fn with_limit_test() {
+        //上限に達したら残りは読まないので、後ろが壊れていても読み込める
+        let s = format!(
+            r#"<rss version="2.0"><channel><title>大量の記事</title>{}<item><title>壊れた"#,
+            (0..500)
+                .map(|i| format!("<item><title>{}件目</title></item>", i))
+                .collect::<String>()
+        );
+        let rss = RSS::with_limit(&s, Some(3)).unwrap();
+        let titles = rss
+            .channel
+            .items
+            .iter()
+            .map(|item| item.title.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["0件目", "1件目", "2件目"]);
+        assert_eq!(rss.channel.title, "大量の記事");
+        assert!(RSS::new(&s).is_err());
+
+        //<rss><channel>の外にあるitemは読まない
+        let rss = RSS::new("<rss><item><title>外</title></item><channel/></rss>").unwrap();
+        assert!(rss.channel.items.is_empty());
     }
 }
@@ -0,0 +1,144 @@
+use crate::feed::Schedule;
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+use std::time;
+
+const MAX_INTERVAL_DAYS: i64 = 365 * 100; //最小間隔の上限 日時の範囲を超えないよう、これより長い間隔はこの日数とみなす
+
+/*
+ * フィードごとに最後に取得した時刻を覚えておき、次に取得し直してよい時刻を決める
+ * 間隔はフィードのttlと設定の最小間隔の長い方で、skipHoursとskipDaysの間は取得しない
+ * 一度も取得していないフィードはすぐに取得する
+ */
+pub struct Scheduler {
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    min_interval: Duration,
+    fetched_at: Option<DateTime<Utc>>,
+    schedule: Schedule,
+}
+
+impl Scheduler {
+    /*フィードの数だけ最小間隔を渡す*/
+    pub fn new(min_intervals: Vec<time::Duration>) -> Self {
+        let max = Duration::days(MAX_INTERVAL_DAYS);
+        let entries = min_intervals
+            .into_iter()
+            .map(|min_interval| Entry {
+                min_interval: Duration::from_std(min_interval).map_or(max, |d| d.min(max)),
+                fetched_at: None,
+                schedule: Schedule::default(),
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /*i番目のフィードをnowに取得したことを記録する*/
+    pub fn fetched(&mut self, i: usize, now: DateTime<Utc>, schedule: Schedule) {
+        let entry = &mut self.entries[i];
+        entry.fetched_at = Some(now);
+        entry.schedule = schedule;
+    }
+
+    /*取得に失敗したときは前回の指定のまま間隔を空けてやり直す*/
+    pub fn postpone(&mut self, i: usize, now: DateTime<Utc>) {
+        self.entries[i].fetched_at = Some(now);
+    }
+
+    /*i番目のフィードを次に取得し直してよい時刻 skipHoursとskipDaysは考えない*/
+    pub fn next_refresh(&self, i: usize) -> Option<DateTime<Utc>> {
+        let entry = &self.entries[i];
+        let ttl = entry
+            .schedule
+            .ttl
+            .map(|ttl| Duration::minutes(i64::from(ttl)))
+            .unwrap_or_else(Duration::zero);
+        entry
+            .fetched_at
+            .map(|fetched_at| fetched_at + ttl.max(entry.min_interval))
+    }
+
+    /*取得してから間もなく、取得し直さなくてよいか*/
+    pub fn is_fresh(&self, i: usize, now: DateTime<Utc>) -> bool {
+        matches!(self.next_refresh(i), Some(next) if now < next)
+    }
+
+    /*nowの時点で取得し直すべきフィードのインデックス*/
+    pub fn due(&self, now: DateTime<Utc>) -> Vec<usize> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(i, entry)| {
+                !matches!(self.next_refresh(*i), Some(next) if next > now)
+                    && !entry.schedule.skips(now)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+impl Schedule {
+    /*skipHoursとskipDaysはGMTで指定される*/
+    pub fn skips(&self, now: DateTime<Utc>) -> bool {
+        self.skip_hours.contains(&now.hour()) || self.skip_days.contains(&now.weekday())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Weekday;
+    #[test]
+    fn due_test() {
+        let mut scheduler = Scheduler::new(vec![time::Duration::from_secs(300); 3]);
+        //2021-08-12は木曜日
+        let now = "2021-08-12T03:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(scheduler.due(now), [0, 1, 2]);
+        assert!(!scheduler.is_fresh(0, now));
+
+        scheduler.fetched(0, now, Schedule::default());
+        scheduler.fetched(
+            1,
+            now,
+            Schedule {
+                ttl: Some(60),
+                ..Schedule::default()
+            },
+        );
+        scheduler.fetched(
+            2,
+            now,
+            Schedule {
+                skip_hours: vec![3],
+                skip_days: vec![Weekday::Sat],
+                ..Schedule::default()
+            },
+        );
+        assert!(scheduler.is_fresh(0, now + Duration::minutes(4)));
+        assert!(scheduler.due(now + Duration::minutes(4)).is_empty());
+        //最小間隔を過ぎても同じ時間帯はskipHoursで取得しない
+        assert_eq!(scheduler.due(now + Duration::minutes(5)), [0]);
+        assert_eq!(scheduler.next_refresh(1), Some(now + Duration::hours(1)));
+        assert_eq!(scheduler.due(now + Duration::hours(1)), [0, 1, 2]);
+        assert_eq!(scheduler.due(now + Duration::days(2)), [0, 1]);
+
+        scheduler.postpone(1, now + Duration::hours(1));
+        assert_eq!(scheduler.next_refresh(1), Some(now + Duration::hours(2)));
+
+        //長すぎる最小間隔でも次の時刻を求められる
+        let mut scheduler = Scheduler::new(vec![
+            time::Duration::MAX,
+            time::Duration::from_secs(10_000_000_000_000),
+        ]);
+        scheduler.fetched(0, now, Schedule::default());
+        scheduler.fetched(1, now, Schedule::default());
+        for i in 0..2 {
+            assert_eq!(
+                scheduler.next_refresh(i),
+                Some(now + Duration::days(MAX_INTERVAL_DAYS))
+            );
+        }
+        assert!(scheduler.due(now + Duration::days(365)).is_empty());
+    }
+}
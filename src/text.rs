@@ -0,0 +1,138 @@
+use rusttype::{point, Font, Scale};
+
+/*行頭に置かない文字(JIS X 4051の終わり括弧類、句読点、小書きの仮名など)*/
+const NO_START: &str = "、。，．,.・：；:;？！?!‐゠–〜～ー゛゜ヽヾゝゞ々〻\
+    ）〕］｝〉》」』】〙〗〟)]}’”»\
+    ぁぃぅぇぉっゃゅょゎゕゖァィゥェォッャュョヮヵヶㇰㇱㇲㇳㇴㇵㇶㇷㇸㇹㇺㇻㇼㇽㇾㇿ\
+    ｡｣､･ｰｧｨｩｪｫｬｭｮｯﾞﾟ";
+
+/*行末に置かない文字(始め括弧類)*/
+const NO_END: &str = "（〔［｛〈《「『【〘〖〝([{‘“«｢";
+
+/*間で分けない、同じ文字が続く記号*/
+const INSEPARABLE: &str = "…‥—―";
+
+/*文字列を描画したときの幅(ドット)*/
+pub fn text_width(font: &Font, s: &str) -> u32 {
+    font.layout(s, Scale { x: 8.0, y: 8.0 }, point(0.0, 0.0))
+        .last()
+        .map(|glyph| {
+            let advance = glyph.unpositioned().h_metrics().advance_width;
+            (glyph.position().x + advance).ceil() as u32
+        })
+        .unwrap_or(0)
+}
+
+/*
+ * 文字列をフォントで描画したときの幅がwidthに収まるよう折り返して行に分ける
+ * 改行はそのまま改行し、英単語の途中では分けず、行頭禁則と行末禁則の文字は前後の文字ごと次の行へ送る
+ * どこでも分けられないほど長い単語は幅に収まるところで分ける
+ */
+pub fn wrap(font: &Font, s: &str, width: u32) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in s.split('\n') {
+        let mut line = Vec::new();
+        let mut wrapped = false; //折り返した直後の行か
+        for c in paragraph.trim_end_matches('\r').chars() {
+            loop {
+                //折り返した行の先頭の空白は詰める
+                if line.is_empty() && wrapped && c.is_whitespace() {
+                    break;
+                }
+                let mut candidate = line.iter().collect::<String>();
+                candidate.push(c);
+                if line.is_empty() || text_width(font, &candidate) <= width {
+                    line.push(c);
+                    break;
+                }
+                wrapped = true;
+                //はみ出す空白はそこで改行して捨てる
+                if c.is_whitespace() {
+                    lines.push(to_line(&line));
+                    line.clear();
+                    break;
+                }
+                //分けられる最も後ろの位置で分け、残りを次の行へ送る
+                let k = (1..=line.len()).rev().find(|&k| {
+                    let next = line.get(k).copied().unwrap_or(c);
+                    can_break(line[k - 1], next)
+                });
+                let rest = match k {
+                    Some(k) => line.split_off(k),
+                    None => Vec::new(),
+                };
+                lines.push(to_line(&line));
+                line = rest.into_iter().skip_while(|c| c.is_whitespace()).collect();
+            }
+        }
+        lines.push(to_line(&line));
+    }
+    lines
+}
+
+/*行末の空白を除いて行にする*/
+fn to_line(chars: &[char]) -> String {
+    chars.iter().collect::<String>().trim_end().to_string()
+}
+
+/*prevとnextの間で改行できるか*/
+fn can_break(prev: char, next: char) -> bool {
+    let inside_word = is_word(prev) && is_word(next);
+    let inseparable = prev == next && INSEPARABLE.contains(prev);
+    !(inside_word || inseparable || NO_START.contains(next) || NO_END.contains(prev))
+}
+
+/*英単語などの、途中で分けない文字か(CJKの文字と空白以外)*/
+fn is_word(c: char) -> bool {
+    !c.is_whitespace() && (c as u32) < 0x2E80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> Font<'static> {
+        let v = Vec::from(include_bytes!("font/misaki_gothic.ttf") as &[u8]);
+        Font::try_from_vec(v).unwrap()
+    }
+
+    #[test]
+    fn wrap_test() {
+        let font = font();
+        //改行はそのまま改行する
+        assert_eq!(
+            wrap(&font, "一行目\r\n\n三行目", 128),
+            ["一行目", "", "三行目"]
+        );
+
+        //英単語の途中では分けない
+        let s = "The quick brown fox jumps over the lazy dog and keeps running";
+        let lines = wrap(&font, s, 128);
+        assert!(lines.len() > 1);
+        assert_eq!(lines.join(" "), s);
+        for line in &lines {
+            assert!(text_width(&font, line) <= 128, "{}", line);
+        }
+
+        //句点は行頭に置かず、前の文字ごと次の行へ送る
+        let s = format!("{}。", "あ".repeat(16));
+        assert_eq!(wrap(&font, &s, 128), ["あ".repeat(15), "あ。".to_string()]);
+        //始め括弧は行末に置かない
+        let s = format!("{}「い」", "あ".repeat(15));
+        assert_eq!(
+            wrap(&font, &s, 128),
+            ["あ".repeat(15), "「い」".to_string()]
+        );
+
+        //半角カナは実際の幅で数える
+        let s = "ｱ".repeat(32);
+        assert_eq!(text_width(&font, &s), 128);
+        assert_eq!(wrap(&font, &s, 128), [s]);
+
+        //幅に収まらない単語は途中で分ける
+        let s = "a".repeat(40);
+        let lines = wrap(&font, &s, 128);
+        assert_eq!(lines.concat(), s);
+        assert_eq!(lines.len(), 2);
+    }
+}